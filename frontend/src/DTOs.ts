@@ -6,8 +6,56 @@ export type RepoDTO = {
     description: string,
     pushed_at: Date,
     readme?: string,
+    topics: string[],
+    language?: string,
+    stargazers_count: number,
+    forks_count: number,
+    license_spdx_id?: string,
+    homepage?: string,
+    created_at?: Date,
+    archived: boolean,
+    fork: boolean,
+    crate_names: string[],
+    crates: CrateDTO[],
+    languages: LanguageDTO[],
     url_safe_name: string,
-    additional_nav_elements: NavBarElement[]
+    additional_nav_elements: NavBarElement[],
+    featured_rank?: number,
+}
+
+export type CrateDTO = {
+    name: string,
+    latest_version: string,
+    downloads: number,
+    recent_downloads?: number,
+    rust_version?: string,
+    edition?: string,
+}
+
+export type LanguageDTO = {
+    language: string,
+    bytes?: number,
+    percentage: number,
+}
+
+export type ReleaseDTO = {
+    id: number,
+    tag_name: string,
+    name?: string,
+    body?: string,
+    html_url: string,
+    published_at?: Date,
+    prerelease: boolean,
+}
+
+export type CommitDTO = {
+    sha: string,
+    headline: string,
+    author: string,
+    committed_at: Date,
+    html_url: string,
+    repo_name: string,
+    repo_url_safe_name: string,
 }
 
 export type NavBarElement = {
@@ -22,5 +70,7 @@ export type BlogPostDTO = {
     sha: string,
     description: string,
     content: string,
+    path: string,
+    updated_at?: Date,
     url_safe_name: string,
 }
\ No newline at end of file
@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS GitHubResponseCache (
+   url TEXT PRIMARY KEY,
+   etag TEXT,
+   last_modified TEXT,
+   link TEXT,
+   body TEXT NOT NULL,
+   updated_at TIMESTAMPTZ NOT NULL
+);
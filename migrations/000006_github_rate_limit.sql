@@ -0,0 +1 @@
+ALTER TABLE GitHubQueryState ADD COLUMN IF NOT EXISTS rate_limit_reset TIMESTAMPTZ;
@@ -0,0 +1,11 @@
+ALTER TABLE BlogPosts ADD COLUMN IF NOT EXISTS path TEXT;
+
+-- Every post so far came from the root of the content repo.
+UPDATE BlogPosts SET path = name || '.md' WHERE path IS NULL;
+
+ALTER TABLE BlogPosts ALTER COLUMN path SET NOT NULL;
+ALTER TABLE BlogPosts ADD CONSTRAINT path_unique UNIQUE (path);
+
+-- The same content can now sit at two paths, and two posts can swap content
+-- in one sync, so the sha is no longer unique.
+ALTER TABLE BlogPosts DROP CONSTRAINT IF EXISTS blogposts_sha_key;
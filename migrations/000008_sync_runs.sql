@@ -0,0 +1,14 @@
+CREATE TABLE IF NOT EXISTS SyncRuns (
+   id SERIAL PRIMARY KEY,
+   trigger TEXT NOT NULL,
+   started_at TIMESTAMPTZ NOT NULL,
+   finished_at TIMESTAMPTZ,
+   succeeded BOOLEAN,
+   repos_upserted INT NOT NULL DEFAULT 0,
+   repos_deleted INT NOT NULL DEFAULT 0,
+   blog_posts_upserted INT NOT NULL DEFAULT 0,
+   blog_posts_deleted INT NOT NULL DEFAULT 0,
+   error TEXT
+);
+
+CREATE INDEX idx_sync_runs_started_at ON SyncRuns(started_at);
@@ -0,0 +1,11 @@
+ALTER TABLE MrAdultRepositories
+   ADD COLUMN IF NOT EXISTS topics TEXT[] NOT NULL DEFAULT '{}',
+   ADD COLUMN IF NOT EXISTS language TEXT,
+   ADD COLUMN IF NOT EXISTS stargazers_count INT NOT NULL DEFAULT 0,
+   ADD COLUMN IF NOT EXISTS forks_count INT NOT NULL DEFAULT 0,
+   ADD COLUMN IF NOT EXISTS license_spdx_id TEXT,
+   ADD COLUMN IF NOT EXISTS homepage TEXT,
+   -- Unknown until the next sync fills it in.
+   ADD COLUMN IF NOT EXISTS created_at TIMESTAMPTZ,
+   ADD COLUMN IF NOT EXISTS archived BOOLEAN NOT NULL DEFAULT FALSE,
+   ADD COLUMN IF NOT EXISTS fork BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,17 @@
+CREATE TABLE IF NOT EXISTS RepoLanguages (
+   repo_id BIGINT NOT NULL REFERENCES MrAdultRepositories(id) ON DELETE CASCADE,
+   language TEXT NOT NULL,
+   -- GitHub counts bytes, while GitLab only reports each language's share of
+   -- the repo, from 0 to 100. Whichever the source doesn't give is NULL.
+   bytes BIGINT,
+   share DOUBLE PRECISION,
+   PRIMARY KEY (repo_id, language)
+);
+
+-- Makes the next sync fetch everything about a repo again, even if it hasn't
+-- changed. The sync clears it once the repo is written.
+ALTER TABLE MrAdultRepositories ADD COLUMN IF NOT EXISTS needs_resync BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- Languages are only fetched for repos that changed, so have the next sync
+-- fill them in for every repo.
+UPDATE MrAdultRepositories SET needs_resync = TRUE;
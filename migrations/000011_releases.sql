@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS Releases (
+   id BIGINT PRIMARY KEY,
+   repo_id BIGINT NOT NULL REFERENCES MrAdultRepositories(id) ON DELETE CASCADE,
+   tag_name TEXT NOT NULL,
+   name TEXT,
+   body TEXT,
+   html_url TEXT NOT NULL,
+   published_at TIMESTAMPTZ,
+   prerelease BOOLEAN NOT NULL DEFAULT FALSE
+);
+
+CREATE INDEX idx_releases_repo_id ON Releases(repo_id);
@@ -0,0 +1,14 @@
+CREATE TABLE IF NOT EXISTS Commits (
+   repo_id BIGINT NOT NULL REFERENCES MrAdultRepositories(id) ON DELETE CASCADE,
+   sha TEXT NOT NULL,
+   headline TEXT NOT NULL,
+   author TEXT NOT NULL,
+   committed_at TIMESTAMPTZ NOT NULL,
+   html_url TEXT NOT NULL,
+   PRIMARY KEY (repo_id, sha)
+);
+
+CREATE INDEX idx_commits_committed_at ON Commits(committed_at);
+
+-- Fill in the commits of the repos that won't change any time soon too.
+UPDATE MrAdultRepositories SET needs_resync = TRUE;
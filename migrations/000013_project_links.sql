@@ -0,0 +1,14 @@
+CREATE TABLE IF NOT EXISTS ProjectLinks (
+   id SERIAL PRIMARY KEY,
+   repo_name TEXT NOT NULL,
+   display_text TEXT NOT NULL,
+   href TEXT NOT NULL,
+   position INT NOT NULL DEFAULT 0
+);
+
+CREATE INDEX idx_project_links_repo_name ON ProjectLinks(LOWER(repo_name));
+
+-- The links that used to be hard-coded in the server.
+INSERT INTO ProjectLinks( repo_name, display_text, href ) VALUES
+   ( 'tree-iterators-rs', 'Crates.io', 'https://crates.io/crates/tree_iterators_rs' ),
+   ( 'json-formatter', 'Crates.io', 'https://crates.io/crates/toy-json-formatter' );
@@ -0,0 +1,4 @@
+ALTER TABLE MrAdultRepositories ADD COLUMN IF NOT EXISTS crate_names TEXT[] NOT NULL DEFAULT '{}';
+
+-- Look for crates in every repo on the next sync, not just the changed ones.
+UPDATE MrAdultRepositories SET needs_resync = TRUE;
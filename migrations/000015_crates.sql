@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS Crates (
+   name TEXT PRIMARY KEY,
+   repo_id BIGINT NOT NULL REFERENCES MrAdultRepositories(id) ON DELETE CASCADE,
+   latest_version TEXT NOT NULL,
+   downloads BIGINT NOT NULL,
+   recent_downloads BIGINT,
+   rust_version TEXT,
+   edition TEXT
+);
+
+CREATE INDEX idx_crates_repo_id ON Crates(repo_id);
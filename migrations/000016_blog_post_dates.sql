@@ -0,0 +1,5 @@
+ALTER TABLE BlogPosts ADD COLUMN IF NOT EXISTS updated_at TIMESTAMPTZ;
+
+-- Posts are only downloaded when their sha changes, so give every post a sha
+-- no source will list to have the next sync fill the dates in.
+UPDATE BlogPosts SET sha = 'resync-' || id;
@@ -1,3 +1,10 @@
 DROP TABLE GitHubQueryState;
+DROP TABLE RepoLanguages;
+DROP TABLE Releases;
+DROP TABLE Commits;
+DROP TABLE Crates;
 DROP TABLE MrAdultRepositories;
-DROP TABLE BlogPosts;
\ No newline at end of file
+DROP TABLE BlogPosts;
+DROP TABLE GitHubResponseCache;
+DROP TABLE SyncRuns;
+DROP TABLE ProjectLinks;
@@ -0,0 +1,298 @@
+use std::{fmt::Display, path::PathBuf, str::FromStr};
+
+use sqlx::types::chrono::{DateTime, NaiveDate, Utc};
+
+/// Runtime configuration for the server. Everything here is read from the
+/// environment (or the .env file) once at startup.
+#[derive(Clone)]
+pub(crate) struct Config {
+    /// Where the projects and blog posts are read from.
+    pub(crate) content_source: ContentSourceConfig,
+    /// A local git repo, bare or not, to read the blog posts from instead of
+    /// the content source.
+    pub(crate) posts_git_repo: Option<PathBuf>,
+    /// The revision of `posts_git_repo` the posts are read at.
+    pub(crate) posts_git_ref: String,
+    /// The root of the GitHub REST API, always with a trailing slash. This can
+    /// point at GitHub Enterprise or a local mock server instead.
+    pub(crate) github_api_url: String,
+    /// The account whose repos are showcased.
+    pub(crate) github_owner: String,
+    /// The repo, owned by `github_owner`, that the blog posts are read from.
+    pub(crate) github_content_repo: String,
+    /// How many items to request per page from paginated GitHub endpoints.
+    /// GitHub caps this at 100.
+    pub(crate) github_per_page: u8,
+    /// How many of each repo's most recent commits to keep for the activity
+    /// feed. GitHub caps this at 100.
+    pub(crate) github_commits_per_repo: u8,
+    /// The root of the crates.io API, always with a trailing slash.
+    pub(crate) crates_io_api_url: String,
+    /// The secret configured on the GitHub webhook. The webhook endpoint is
+    /// disabled unless this is set.
+    pub(crate) github_webhook_secret: Option<String>,
+    /// The credentials sent with every GitHub API call.
+    pub(crate) github_auth: GitHubAuth,
+    /// Whether to list private repos as well as public ones. This needs
+    /// credentials that can see them.
+    pub(crate) github_include_private: bool,
+    /// Which of the owner's repos are showcased.
+    pub(crate) repo_filter: RepoFilter,
+    /// The names of the repos to list ahead of the rest, in the order to list
+    /// them.
+    pub(crate) featured_repos: Vec<String>,
+    /// The bearer token for the admin endpoints. They're disabled unless this
+    /// is set.
+    pub(crate) admin_token: Option<String>,
+    /// How long the data can go without a sync.
+    pub(crate) sync_interval_secs: u64,
+    /// The most the scheduler will add, at random, on top of the interval.
+    pub(crate) sync_jitter_secs: u64,
+    /// How long the sync lock's connection can go untouched before Postgres
+    /// takes the lock away. A syncing replica touches it every third of this.
+    /// Also how long a requested sync waits for the lock.
+    pub(crate) sync_lock_timeout_secs: u64,
+}
+
+/// Which backend the projects and blog posts come from.
+#[derive(Clone)]
+pub(crate) enum ContentSourceConfig {
+    /// The owner's GitHub repos, with the posts in `github_content_repo`.
+    GitHub,
+    /// Directories on this machine, for working offline. Every directory in
+    /// `projects_dir` is a project, and every markdown file under `posts_dir`
+    /// is a post.
+    Local {
+        projects_dir: PathBuf,
+        posts_dir: PathBuf,
+    },
+    /// The projects in a GitLab group or user namespace, with the posts in
+    /// `content_project`.
+    GitLab {
+        /// The root of the GitLab REST API, always with a trailing slash.
+        api_url: String,
+        namespace: String,
+        content_project: String,
+        token: Option<String>,
+    },
+}
+
+impl ContentSourceConfig {
+    fn from_env() -> Self {
+        match get_env("CONTENT_SOURCE").as_deref() {
+            None | Some("github") => Self::GitHub,
+            Some("local") => Self::Local {
+                projects_dir: get_env("LOCAL_PROJECTS_DIR")
+                    .unwrap_or_else(|| "content/projects".to_string())
+                    .into(),
+                posts_dir: get_env("LOCAL_POSTS_DIR")
+                    .unwrap_or_else(|| "content/posts".to_string())
+                    .into(),
+            },
+            Some("gitlab") => {
+                let mut api_url = get_env("GITLAB_API_URL")
+                    .unwrap_or_else(|| "https://gitlab.com/api/v4/".to_string());
+                if !api_url.ends_with('/') {
+                    api_url.push('/');
+                }
+
+                Self::GitLab {
+                    api_url,
+                    namespace: get_env("GITLAB_NAMESPACE").unwrap_or_else(|| {
+                        panic!("CONTENT_SOURCE is gitlab, so GITLAB_NAMESPACE must be set")
+                    }),
+                    content_project: get_env("GITLAB_CONTENT_PROJECT")
+                        .unwrap_or_else(|| "blog-posts".to_string()),
+                    token: get_env("GITLAB_TOKEN"),
+                }
+            }
+            Some(other) => panic!(
+                "CONTENT_SOURCE has an invalid value. Expected github, local or gitlab, got {}",
+                other
+            ),
+        }
+    }
+}
+
+/// How we authenticate with the GitHub API.
+#[derive(Clone)]
+pub(crate) enum GitHubAuth {
+    /// Anonymous requests, which GitHub limits to 60 an hour.
+    Anonymous,
+    /// A personal access token.
+    Token(String),
+    /// A GitHub App installation. We sign a JWT with the app's private key and
+    /// trade it for a short-lived installation token.
+    App {
+        app_id: String,
+        private_key_pem: String,
+        installation_id: u64,
+    },
+}
+
+/// Rules for which repos make it onto the site. A repo has to pass every one
+/// of them.
+#[derive(Clone, Default)]
+pub(crate) struct RepoFilter {
+    /// Name globs a repo has to match one of. Empty means every name.
+    pub(crate) include_names: Vec<String>,
+    /// Name globs that keep a repo off the site.
+    pub(crate) exclude_names: Vec<String>,
+    /// Topics a repo has to have one of. Empty means any topics, or none.
+    pub(crate) include_topics: Vec<String>,
+    /// Topics that keep a repo off the site.
+    pub(crate) exclude_topics: Vec<String>,
+    pub(crate) include_forks: bool,
+    pub(crate) include_archived: bool,
+    /// Repos that haven't been pushed to since this are left off.
+    pub(crate) active_since: Option<DateTime<Utc>>,
+}
+
+impl RepoFilter {
+    fn from_env() -> Self {
+        Self {
+            include_names: get_list_env("REPO_INCLUDE"),
+            exclude_names: get_list_env("REPO_EXCLUDE"),
+            include_topics: get_list_env("REPO_INCLUDE_TOPICS"),
+            exclude_topics: get_list_env("REPO_EXCLUDE_TOPICS"),
+            include_forks: parse_env("REPO_INCLUDE_FORKS").unwrap_or(true),
+            include_archived: parse_env("REPO_INCLUDE_ARCHIVED").unwrap_or(true),
+            active_since: get_env("REPO_ACTIVE_SINCE").map(|value| {
+                // Take a plain date as midnight UTC.
+                match value.parse::<NaiveDate>() {
+                    Ok(date) => date
+                        .and_hms_opt(0, 0, 0)
+                        .expect("midnight to be a valid time")
+                        .and_utc(),
+                    Err(_) => value.parse().unwrap_or_else(|err| {
+                        panic!("REPO_ACTIVE_SINCE has an invalid value. Error: \n{}", err)
+                    }),
+                }
+            }),
+        }
+    }
+}
+
+impl GitHubAuth {
+    fn from_env() -> Self {
+        if let Some(token) = get_env("GITHUB_TOKEN") {
+            return Self::Token(token);
+        }
+
+        let app_id = match get_env("GITHUB_APP_ID") {
+            None => return Self::Anonymous,
+            Some(app_id) => app_id,
+        };
+
+        let private_key_pem = match (
+            get_env("GITHUB_APP_PRIVATE_KEY"),
+            get_env("GITHUB_APP_PRIVATE_KEY_PATH"),
+        ) {
+            // Keys pasted into a .env file usually have their newlines escaped.
+            (Some(pem), _) => pem.replace("\\n", "\n"),
+            (None, Some(path)) => std::fs::read_to_string(&path).unwrap_or_else(|err| {
+                panic!("Failed to read GITHUB_APP_PRIVATE_KEY_PATH. Error: \n{}", err)
+            }),
+            (None, None) => panic!(
+                "GITHUB_APP_ID is set, so GITHUB_APP_PRIVATE_KEY or GITHUB_APP_PRIVATE_KEY_PATH must be set"
+            ),
+        };
+
+        let installation_id = parse_env("GITHUB_APP_INSTALLATION_ID").unwrap_or_else(|| {
+            panic!("GITHUB_APP_ID is set, so GITHUB_APP_INSTALLATION_ID must be set")
+        });
+
+        Self::App {
+            app_id,
+            private_key_pem,
+            installation_id,
+        }
+    }
+}
+
+impl Config {
+    pub(crate) fn from_env() -> Self {
+        let mut github_api_url =
+            get_env("GITHUB_API_URL").unwrap_or_else(|| "https://api.github.com/".to_string());
+        if !github_api_url.ends_with('/') {
+            github_api_url.push('/');
+        }
+
+        let mut crates_io_api_url =
+            get_env("CRATES_IO_API_URL").unwrap_or_else(|| "https://crates.io/".to_string());
+        if !crates_io_api_url.ends_with('/') {
+            crates_io_api_url.push('/');
+        }
+
+        let config = Self {
+            content_source: ContentSourceConfig::from_env(),
+            posts_git_repo: get_env("POSTS_GIT_REPO").map(PathBuf::from),
+            posts_git_ref: get_env("POSTS_GIT_REF").unwrap_or_else(|| "HEAD".to_string()),
+            github_api_url,
+            github_owner: get_env("GITHUB_OWNER").unwrap_or_else(|| "mr-adult".to_string()),
+            github_content_repo: get_env("GITHUB_CONTENT_REPO")
+                .unwrap_or_else(|| "blog-posts".to_string()),
+            github_per_page: parse_env("GITHUB_PER_PAGE").unwrap_or(100).clamp(1, 100),
+            github_commits_per_repo: parse_env("GITHUB_COMMITS_PER_REPO")
+                .unwrap_or(10)
+                .clamp(1, 100),
+            crates_io_api_url,
+            github_webhook_secret: get_env("GITHUB_WEBHOOK_SECRET"),
+            github_auth: GitHubAuth::from_env(),
+            github_include_private: parse_env("GITHUB_INCLUDE_PRIVATE").unwrap_or(false),
+            repo_filter: RepoFilter::from_env(),
+            featured_repos: get_list_env("FEATURED_REPOS"),
+            admin_token: get_env("ADMIN_TOKEN"),
+            sync_interval_secs: parse_env("SYNC_INTERVAL_SECS").unwrap_or(60 * 60),
+            sync_jitter_secs: parse_env("SYNC_JITTER_SECS").unwrap_or(5 * 60),
+            sync_lock_timeout_secs: parse_env("SYNC_LOCK_TIMEOUT_SECS").unwrap_or(10 * 60),
+        };
+
+        if config.github_include_private && matches!(config.github_auth, GitHubAuth::Anonymous) {
+            panic!("GITHUB_INCLUDE_PRIVATE needs GITHUB_TOKEN or GitHub App credentials");
+        }
+
+        config
+    }
+
+    /// Where the repo sits among the featured ones, or `None` if it isn't
+    /// featured.
+    pub(crate) fn featured_rank(&self, repo_name: &str) -> Option<usize> {
+        self.featured_repos
+            .iter()
+            .position(|featured| featured.eq_ignore_ascii_case(repo_name))
+    }
+}
+
+/// Reads an optional environment variable, treating an empty value as unset.
+fn get_env(key: &str) -> Option<String> {
+    std::env::var(key).ok().filter(|value| !value.is_empty())
+}
+
+/// Reads a comma separated list from an environment variable. Unset means an
+/// empty list.
+fn get_list_env(key: &str) -> Vec<String> {
+    get_env(key)
+        .map(|value| {
+            value
+                .split(',')
+                .map(|item| item.trim().to_string())
+                .filter(|item| !item.is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Reads and parses an optional environment variable. A variable that is set
+/// but can't be parsed is a deployment mistake, so we refuse to start.
+fn parse_env<T>(key: &str) -> Option<T>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    let value = get_env(key)?;
+    match value.parse() {
+        Ok(parsed) => Some(parsed),
+        Err(err) => panic!("{} has an invalid value. Error: \n{}", key, err),
+    }
+}
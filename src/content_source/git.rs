@@ -0,0 +1,356 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use sqlx::types::chrono::{DateTime, Utc};
+
+use super::{get_post_name, ContentSource};
+use crate::{
+    error::Error,
+    github::{commits::Commit, languages::LanguageUsage, releases::Release, FileMetadata, Repo},
+    AppState,
+};
+
+/// Reads the blog posts straight out of a git repo on this machine, like a
+/// clone mounted into the container, and leaves the projects to another
+/// source. The repo can be bare. Nothing is fetched, so whatever updates the
+/// repo decides what gets synced.
+pub(crate) struct GitPostsSource {
+    /// Where the projects come from.
+    projects: Arc<dyn ContentSource>,
+    repo_path: PathBuf,
+    /// The revision the posts are read at, like "HEAD" or "main".
+    git_ref: String,
+    /// Dates from earlier listings, so only new or changed posts cost a walk
+    /// through the history.
+    dates: Arc<Mutex<PostDates>>,
+}
+
+/// When each post was last changed, keyed by its path and blob id.
+type PostDates = HashMap<(String, gix::ObjectId), DateTime<Utc>>;
+
+impl GitPostsSource {
+    pub(crate) fn new(projects: Arc<dyn ContentSource>, repo_path: PathBuf, git_ref: String) -> Self {
+        Self {
+            projects,
+            repo_path,
+            git_ref,
+            dates: Arc::default(),
+        }
+    }
+
+    /// Runs `read` against the repo on a blocking thread, since gitoxide does
+    /// its reads synchronously.
+    async fn read_repo<T, F>(&self, read: F) -> Result<T, Error>
+    where
+        T: Send + 'static,
+        F: FnOnce(&gix::Repository, &str) -> Result<T, String> + Send + 'static,
+    {
+        let repo_path = self.repo_path.clone();
+        let git_ref = self.git_ref.clone();
+        let to_error = |message: String| Error::Git {
+            path: self.repo_path.display().to_string(),
+            message,
+        };
+
+        tokio::task::spawn_blocking(move || {
+            let repo = gix::open(&repo_path).map_err(|err| err.to_string())?;
+            read(&repo, &git_ref)
+        })
+        .await
+        .map_err(|err| to_error(err.to_string()))?
+        .map_err(to_error)
+    }
+}
+
+#[async_trait]
+impl ContentSource for GitPostsSource {
+    async fn list_projects(&self, state: &AppState) -> Result<Vec<Repo>, Error> {
+        self.projects.list_projects(state).await
+    }
+
+    /// Lists every markdown file in the tree at the configured ref. The sha is
+    /// the blob's, and the date is when the last commit to change the file was
+    /// made.
+    async fn list_posts(&self, _state: &AppState) -> Result<Option<Vec<FileMetadata>>, Error> {
+        let cached_dates = self.dates.clone();
+        let posts = self
+            .read_repo(move |repo, git_ref| {
+                let commit = repo
+                    .rev_parse_single(git_ref)
+                    .map_err(|err| err.to_string())?
+                    .object()
+                    .map_err(|err| err.to_string())?
+                    .peel_to_commit()
+                    .map_err(|err| err.to_string())?;
+                let tree = commit.tree().map_err(|err| err.to_string())?;
+
+                let mut recorder = gix::traverse::tree::Recorder::default();
+                tree.traverse()
+                    .breadthfirst(&mut recorder)
+                    .map_err(|err| err.to_string())?;
+                let blobs = recorder
+                    .records
+                    .into_iter()
+                    .filter(|entry| entry.mode.is_blob() && entry.filepath.ends_with(b".md"))
+                    .map(|entry| (entry.filepath.to_string(), entry.oid))
+                    .collect::<Vec<_>>();
+
+                let mut cached_dates = cached_dates
+                    .lock()
+                    .expect("the dates lock to not be poisoned");
+                let uncached = blobs
+                    .iter()
+                    .filter(|(path, oid)| !cached_dates.contains_key(&(path.clone(), *oid)))
+                    .cloned()
+                    .collect::<Vec<_>>();
+                let mut new_dates = get_last_modified(repo, commit.id, &uncached)?;
+
+                // Rebuilding the cache from this listing drops the entries of
+                // posts that were changed or removed.
+                let mut dates = HashMap::new();
+                let posts = blobs
+                    .into_iter()
+                    .map(|(path, oid)| {
+                        let key = (path.clone(), oid);
+                        let updated_at = cached_dates
+                            .remove(&key)
+                            .or_else(|| new_dates.remove(&path));
+                        if let Some(updated_at) = updated_at {
+                            dates.insert(key, updated_at);
+                        }
+
+                        FileMetadata {
+                            name: get_post_name(&path),
+                            sha: oid.to_string(),
+                            updated_at,
+                            path,
+                        }
+                    })
+                    .collect();
+                *cached_dates = dates;
+                Ok(posts)
+            })
+            .await?;
+
+        Ok(Some(posts))
+    }
+
+    async fn fetch_file(
+        &self,
+        state: &AppState,
+        project: Option<&Repo>,
+        path: &str,
+    ) -> Result<Option<String>, Error> {
+        if project.is_some() {
+            return self.projects.fetch_file(state, project, path).await;
+        }
+
+        let path = path.to_string();
+        self.read_repo(move |repo, git_ref| {
+            let tree = repo
+                .rev_parse_single(git_ref)
+                .map_err(|err| err.to_string())?
+                .object()
+                .map_err(|err| err.to_string())?
+                .peel_to_tree()
+                .map_err(|err| err.to_string())?;
+            let entry = match tree
+                .lookup_entry_by_path(Path::new(&path))
+                .map_err(|err| err.to_string())?
+            {
+                None => return Ok(None),
+                Some(entry) => entry,
+            };
+
+            let blob = entry.object().map_err(|err| err.to_string())?;
+            Ok(Some(String::from_utf8_lossy(&blob.data).to_string()))
+        })
+        .await
+    }
+
+    /// Reads the post's blob by the id it was listed with, so the content
+    /// matches the listing even if the ref has moved on since.
+    async fn fetch_post(
+        &self,
+        _state: &AppState,
+        post: &FileMetadata,
+    ) -> Result<Option<String>, Error> {
+        let oid = gix::ObjectId::from_hex(post.sha.as_bytes()).map_err(|err| Error::Git {
+            path: self.repo_path.display().to_string(),
+            message: format!("{} has an invalid blob id. Error: {}", post.path, err),
+        })?;
+
+        self.read_repo(move |repo, _| {
+            let blob = repo.find_blob(oid).map_err(|err| err.to_string())?;
+            Ok(Some(String::from_utf8_lossy(&blob.data).to_string()))
+        })
+        .await
+    }
+
+    async fn list_dirs(
+        &self,
+        state: &AppState,
+        project: &Repo,
+        path: &str,
+    ) -> Result<Vec<String>, Error> {
+        self.projects.list_dirs(state, project, path).await
+    }
+
+    async fn list_languages(
+        &self,
+        state: &AppState,
+        project: &Repo,
+    ) -> Result<Vec<LanguageUsage>, Error> {
+        self.projects.list_languages(state, project).await
+    }
+
+    async fn list_releases(&self, state: &AppState, project: &Repo) -> Result<Vec<Release>, Error> {
+        self.projects.list_releases(state, project).await
+    }
+
+    async fn list_commits(&self, state: &AppState, project: &Repo) -> Result<Vec<Commit>, Error> {
+        self.projects.list_commits(state, project).await
+    }
+}
+
+/// Walks the first-parent history back from `tip` until it has found, for
+/// each of the blobs, the newest commit that changed it. A change merged in
+/// from another branch dates from the merge.
+fn get_last_modified(
+    repo: &gix::Repository,
+    tip: gix::ObjectId,
+    blobs: &[(String, gix::ObjectId)],
+) -> Result<HashMap<String, DateTime<Utc>>, String> {
+    let mut dates = HashMap::new();
+    let walk = repo
+        .rev_walk([tip])
+        .first_parent_only()
+        .all()
+        .map_err(|err| err.to_string())?;
+
+    for info in walk {
+        if dates.len() == blobs.len() {
+            break;
+        }
+
+        let info = info.map_err(|err| err.to_string())?;
+        let commit = info.object().map_err(|err| err.to_string())?;
+        let tree = commit.tree().map_err(|err| err.to_string())?;
+        // A shallow clone's oldest commit still names its parent, but the
+        // parent isn't there. Everything left dates from that commit then.
+        let parent = match info.parent_ids().next() {
+            None => None,
+            Some(parent_id) => parent_id.try_object().map_err(|err| err.to_string())?,
+        };
+        let parent_tree = match parent {
+            None => repo.empty_tree(),
+            Some(parent) => parent.peel_to_tree().map_err(|err| err.to_string())?,
+        };
+        let time = commit.time().map_err(|err| err.to_string())?;
+
+        for (path, _) in blobs {
+            if dates.contains_key(path) {
+                continue;
+            }
+
+            let id_at = |tree: &gix::Tree<'_>| {
+                tree.lookup_entry_by_path(Path::new(path))
+                    .map(|entry| entry.map(|entry| entry.object_id()))
+                    .map_err(|err| err.to_string())
+            };
+            if id_at(&tree)? != id_at(&parent_tree)? {
+                if let Some(date) = DateTime::from_timestamp(time.seconds, 0) {
+                    dates.insert(path.clone(), date);
+                }
+            }
+        }
+    }
+
+    Ok(dates)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+
+    use super::*;
+
+    /// Runs git in `dir`, with every commit made at `date`.
+    fn git(dir: &Path, date: &str, args: &[&str]) {
+        let status = Command::new("git")
+            .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+            .args(["-c", "commit.gpgsign=false"])
+            .args(args)
+            .current_dir(dir)
+            .env("GIT_AUTHOR_DATE", date)
+            .env("GIT_COMMITTER_DATE", date)
+            .status()
+            .expect("git to run");
+        assert!(status.success(), "git {:?} failed", args);
+    }
+
+    /// A repo where a.md was added on January 1st, and b.md was added then
+    /// and changed on February 1st.
+    fn make_repo(dir: &Path) {
+        let _ = std::fs::remove_dir_all(dir);
+        std::fs::create_dir_all(dir).expect("the repo dir to be created");
+        git(dir, "2024-01-01T00:00:00Z", &["init", "-q"]);
+        std::fs::write(dir.join("a.md"), "a").expect("a.md to be written");
+        std::fs::write(dir.join("b.md"), "b").expect("b.md to be written");
+        git(dir, "2024-01-01T00:00:00Z", &["add", "."]);
+        git(dir, "2024-01-01T00:00:00Z", &["commit", "-q", "-m", "Add posts"]);
+        std::fs::write(dir.join("b.md"), "b, edited").expect("b.md to be written");
+        git(dir, "2024-02-01T00:00:00Z", &["commit", "-q", "-am", "Edit b"]);
+    }
+
+    /// The last-modified date of each of `paths` at HEAD, as a date.
+    fn get_dates(repo_path: &Path, paths: &[&str]) -> Vec<String> {
+        let repo = gix::open(repo_path).expect("the repo to open");
+        let commit = repo.head_commit().expect("HEAD to be a commit");
+        let tree = commit.tree().expect("the commit to have a tree");
+        let blobs = paths
+            .iter()
+            .map(|path| {
+                let entry = tree
+                    .lookup_entry_by_path(Path::new(path))
+                    .expect("the tree to be readable")
+                    .expect("the file to be in the tree");
+                (path.to_string(), entry.object_id())
+            })
+            .collect::<Vec<_>>();
+
+        let dates = get_last_modified(&repo, commit.id, &blobs).expect("the history to be read");
+        paths
+            .iter()
+            .map(|path| dates[*path].format("%Y-%m-%d").to_string())
+            .collect()
+    }
+
+    #[test]
+    fn dates_come_from_the_last_commit_to_change_a_file() {
+        let dir = std::env::temp_dir().join(format!("git-posts-full-{}", std::process::id()));
+        make_repo(&dir);
+
+        assert_eq!(get_dates(&dir, &["a.md", "b.md"]), vec!["2024-01-01", "2024-02-01"]);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn shallow_clones_date_older_files_from_their_oldest_commit() {
+        let dir = std::env::temp_dir().join(format!("git-posts-shallow-{}", std::process::id()));
+        let origin = dir.join("origin");
+        make_repo(&origin);
+        let url = format!("file://{}", origin.display());
+        git(&dir, "2024-03-01T00:00:00Z", &["clone", "-q", "--depth", "1", &url, "clone"]);
+
+        assert_eq!(
+            get_dates(&dir.join("clone"), &["a.md", "b.md"]),
+            vec!["2024-02-01", "2024-02-01"]
+        );
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
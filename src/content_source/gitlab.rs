@@ -0,0 +1,425 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use async_trait::async_trait;
+use reqwest::{
+    header::{HeaderMap, HeaderValue, LINK},
+    Client, ClientBuilder, StatusCode, Url,
+};
+use serde_derive::Deserialize;
+use sqlx::types::chrono::{DateTime, Utc};
+
+use super::{get_post_name, get_stable_id, ContentSource};
+use crate::{
+    error::Error,
+    github::{
+        commits::Commit, decode, get_next_page_url, languages::LanguageUsage, releases::Release,
+        FileMetadata, Repo,
+    },
+    AppState,
+};
+
+/// GitLab caps paginated endpoints at 100 items a page.
+const PER_PAGE: &str = "100";
+/// How many of a project's most recent releases we keep, same as for GitHub.
+const RELEASES_PER_PROJECT: &str = "10";
+
+/// Reads the projects from a GitLab group or user, and the blog posts from one
+/// of its projects. This works with gitlab.com and self-hosted instances alike.
+pub(crate) struct GitLabSource {
+    client: Client,
+    /// The root of the GitLab REST API, always with a trailing slash.
+    api_url: Url,
+    namespace: String,
+    content_project: String,
+}
+
+#[derive(Deserialize)]
+struct Namespace {
+    /// Either "group" or "user".
+    kind: String,
+}
+
+#[derive(Deserialize)]
+struct GitLabProject {
+    id: i64,
+    path: String,
+    description: Option<String>,
+    web_url: String,
+    last_activity_at: DateTime<Utc>,
+    created_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    star_count: i32,
+    #[serde(default)]
+    forks_count: i32,
+    #[serde(default)]
+    topics: Vec<String>,
+    #[serde(default)]
+    archived: bool,
+    /// Only there for forks. We don't need anything out of it.
+    forked_from_project: Option<serde_json::Value>,
+    default_branch: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TreeEntry {
+    /// The git blob sha.
+    id: String,
+    name: String,
+    r#type: String,
+    path: String,
+}
+
+#[derive(Deserialize)]
+struct GitLabRelease {
+    tag_name: String,
+    name: Option<String>,
+    description: Option<String>,
+    released_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    upcoming_release: bool,
+    _links: ReleaseLinks,
+}
+
+#[derive(Deserialize)]
+struct ReleaseLinks {
+    #[serde(rename = "self")]
+    html_url: String,
+}
+
+#[derive(Deserialize)]
+struct GitLabCommit {
+    id: String,
+    title: String,
+    author_name: String,
+    committed_date: DateTime<Utc>,
+    web_url: String,
+}
+
+/// A successful response, with the `Link` header for paginated endpoints.
+struct GitLabResponse {
+    body: String,
+    link: Option<String>,
+}
+
+impl GitLabSource {
+    pub(crate) fn new(
+        api_url: String,
+        namespace: String,
+        content_project: String,
+        token: Option<String>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let api_url = Url::parse(&api_url)?;
+        if api_url.cannot_be_a_base() {
+            return Err(format!("GITLAB_API_URL has to be a base URL, got {}", api_url).into());
+        }
+
+        let mut headers = HeaderMap::new();
+        if let Some(token) = token {
+            let mut token = HeaderValue::from_str(&token)?;
+            token.set_sensitive(true);
+            headers.insert("PRIVATE-TOKEN", token);
+        }
+
+        let client = ClientBuilder::new()
+            .danger_accept_invalid_certs(crate::ACCEPT_INVALID_CERTS)
+            .timeout(Duration::from_secs(5))
+            .user_agent("adamfortune.com server")
+            .default_headers(headers)
+            .build()?;
+
+        Ok(Self {
+            client,
+            api_url,
+            namespace,
+            content_project,
+        })
+    }
+
+    /// Builds an API URL out of path segments. Each segment is percent-encoded,
+    /// slashes included, which is how GitLab wants project and file paths.
+    fn endpoint(&self, segments: &[&str], query: &[(&str, &str)]) -> Url {
+        let mut url = self.api_url.clone();
+        url.path_segments_mut()
+            .expect("new to have checked the API URL is a base URL")
+            .pop_if_empty()
+            .extend(segments);
+        if !query.is_empty() {
+            url.query_pairs_mut().extend_pairs(query);
+        }
+        url
+    }
+
+    /// The full path of a project in the namespace, like "group/project".
+    fn project_path(&self, project: Option<&Repo>) -> String {
+        let name = match project {
+            None => &self.content_project,
+            Some(project) => &project.name,
+        };
+        format!("{}/{}", self.namespace, name)
+    }
+
+    /// Sends a GET, treating a 404 as `None`.
+    async fn get(&self, url: &str) -> Result<Option<GitLabResponse>, Error> {
+        let to_error = |message: String| Error::Upstream {
+            service: "GitLab",
+            url: url.to_string(),
+            message,
+        };
+
+        let response = self
+            .client
+            .get(url)
+            .send()
+            .await
+            .map_err(|err| to_error(err.to_string()))?;
+
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !response.status().is_success() {
+            return Err(to_error(response.status().to_string()));
+        }
+
+        let link = response
+            .headers()
+            .get(LINK)
+            .and_then(|link| link.to_str().ok())
+            .map(str::to_string);
+        let body = response
+            .text()
+            .await
+            .map_err(|err| to_error(err.to_string()))?;
+        Ok(Some(GitLabResponse { body, link }))
+    }
+
+    /// Gets every page of a paginated endpoint. If any page fails to load, the
+    /// whole listing fails.
+    async fn get_all<T: serde::de::DeserializeOwned>(
+        &self,
+        url: Url,
+    ) -> Result<Option<Vec<T>>, Error> {
+        let mut items = Vec::new();
+        let mut next_page_url = Some(url.to_string());
+        while let Some(page_url) = next_page_url {
+            let response = match self.get(&page_url).await? {
+                None => return Ok(None),
+                Some(response) => response,
+            };
+
+            items.extend(decode::<Vec<T>>(&page_url, &response.body)?);
+            next_page_url = response.link.as_deref().and_then(get_next_page_url);
+        }
+
+        Ok(Some(items))
+    }
+}
+
+#[async_trait]
+impl ContentSource for GitLabSource {
+    /// Lists the projects directly in the namespace. Projects in subgroups
+    /// aren't included.
+    async fn list_projects(&self, _state: &AppState) -> Result<Vec<Repo>, Error> {
+        let namespace_url = self.endpoint(&["namespaces", &self.namespace], &[]).to_string();
+        let namespace = match self.get(&namespace_url).await? {
+            None => return Err(Error::NotFound(format!("GitLab namespace {}", self.namespace))),
+            Some(response) => decode::<Namespace>(&namespace_url, &response.body)?,
+        };
+        let kind = if namespace.kind == "group" {
+            "groups"
+        } else {
+            "users"
+        };
+
+        let projects_url = self.endpoint(
+            &[kind, &self.namespace, "projects"],
+            &[("per_page", PER_PAGE)],
+        );
+        let projects = self
+            .get_all::<GitLabProject>(projects_url.clone())
+            .await?
+            .ok_or_else(|| Error::NotFound(projects_url.to_string()))?;
+
+        Ok(projects
+            .into_iter()
+            // The content project holds the blog posts. It isn't a project.
+            .filter(|project| project.path != self.content_project)
+            .map(|project| Repo {
+                id: project.id,
+                url: self
+                    .endpoint(&["projects", &project.id.to_string()], &[])
+                    .to_string(),
+                name: project.path,
+                html_url: project.web_url,
+                description: project.description.unwrap_or_default(),
+                pushed_at: project.last_activity_at,
+                topics: project.topics,
+                stargazers_count: project.star_count,
+                forks_count: project.forks_count,
+                created_at: project.created_at,
+                archived: project.archived,
+                fork: project.forked_from_project.is_some(),
+                default_branch: project.default_branch,
+                ..Default::default()
+            })
+            .collect())
+    }
+
+    /// Walks the content project's whole tree and returns every markdown file
+    /// in it, including the ones in folders.
+    async fn list_posts(&self, _state: &AppState) -> Result<Option<Vec<FileMetadata>>, Error> {
+        let tree_url = self.endpoint(
+            &["projects", &self.project_path(None), "repository", "tree"],
+            &[("recursive", "true"), ("per_page", PER_PAGE)],
+        );
+        let tree = match self.get_all::<TreeEntry>(tree_url).await? {
+            None => return Ok(None),
+            Some(tree) => tree,
+        };
+
+        Ok(Some(
+            tree.into_iter()
+                .filter(|entry| entry.r#type == "blob" && entry.name.ends_with(".md"))
+                .map(|entry| FileMetadata {
+                    name: get_post_name(&entry.path),
+                    sha: entry.id,
+                    path: entry.path,
+                    updated_at: None,
+                })
+                .collect(),
+        ))
+    }
+
+    async fn fetch_file(
+        &self,
+        _state: &AppState,
+        project: Option<&Repo>,
+        path: &str,
+    ) -> Result<Option<String>, Error> {
+        let git_ref = project
+            .and_then(|project| project.default_branch.as_deref())
+            .unwrap_or("HEAD");
+        let file_url = self
+            .endpoint(
+                &[
+                    "projects",
+                    &self.project_path(project),
+                    "repository",
+                    "files",
+                    path,
+                    "raw",
+                ],
+                &[("ref", git_ref)],
+            )
+            .to_string();
+
+        Ok(self.get(&file_url).await?.map(|response| response.body))
+    }
+
+    async fn list_dirs(
+        &self,
+        _state: &AppState,
+        project: &Repo,
+        path: &str,
+    ) -> Result<Vec<String>, Error> {
+        let tree_url = self.endpoint(
+            &["projects", &self.project_path(Some(project)), "repository", "tree"],
+            &[("path", path), ("per_page", PER_PAGE)],
+        );
+        let tree = self
+            .get_all::<TreeEntry>(tree_url)
+            .await?
+            .unwrap_or_default();
+
+        Ok(tree
+            .into_iter()
+            .filter(|entry| entry.r#type == "tree")
+            .map(|entry| entry.name)
+            .collect())
+    }
+
+    /// GitLab only reports each language's share of the project, as a
+    /// percentage, so there are no byte counts.
+    async fn list_languages(
+        &self,
+        _state: &AppState,
+        project: &Repo,
+    ) -> Result<Vec<LanguageUsage>, Error> {
+        let languages_url = self
+            .endpoint(&["projects", &project.id.to_string(), "languages"], &[])
+            .to_string();
+        let languages = match self.get(&languages_url).await? {
+            None => return Ok(Vec::new()),
+            Some(response) => decode::<BTreeMap<String, f64>>(&languages_url, &response.body)?,
+        };
+
+        let mut languages = languages
+            .into_iter()
+            .map(|(language, share)| LanguageUsage {
+                language,
+                bytes: None,
+                share: Some(share),
+            })
+            .collect::<Vec<_>>();
+        languages.sort_by(|left, right| {
+            right
+                .share
+                .unwrap_or_default()
+                .total_cmp(&left.share.unwrap_or_default())
+        });
+        Ok(languages)
+    }
+
+    async fn list_releases(&self, _state: &AppState, project: &Repo) -> Result<Vec<Release>, Error> {
+        let releases_url = self
+            .endpoint(
+                &["projects", &project.id.to_string(), "releases"],
+                &[("per_page", RELEASES_PER_PROJECT)],
+            )
+            .to_string();
+        let releases = match self.get(&releases_url).await? {
+            None => return Ok(Vec::new()),
+            Some(response) => decode::<Vec<GitLabRelease>>(&releases_url, &response.body)?,
+        };
+
+        Ok(releases
+            .into_iter()
+            .map(|release| Release {
+                // GitLab releases don't have an id of their own.
+                id: get_stable_id(&format!("gitlab/{}/{}", project.id, release.tag_name)),
+                tag_name: release.tag_name,
+                name: release.name,
+                body: release.description,
+                html_url: release._links.html_url,
+                published_at: release.released_at,
+                prerelease: release.upcoming_release,
+                draft: false,
+            })
+            .collect())
+    }
+
+    async fn list_commits(&self, state: &AppState, project: &Repo) -> Result<Vec<Commit>, Error> {
+        let per_page = state.config.github_commits_per_repo.to_string();
+        let commits_url = self
+            .endpoint(
+                &["projects", &project.id.to_string(), "repository", "commits"],
+                &[("per_page", &per_page)],
+            )
+            .to_string();
+        let commits = match self.get(&commits_url).await? {
+            None => return Ok(Vec::new()),
+            Some(response) => decode::<Vec<GitLabCommit>>(&commits_url, &response.body)?,
+        };
+
+        Ok(commits
+            .into_iter()
+            .map(|commit| Commit {
+                sha: commit.id,
+                headline: commit.title,
+                author: commit.author_name,
+                committed_at: commit.committed_date,
+                html_url: commit.web_url,
+                repo_name: project.name.clone(),
+            })
+            .collect())
+    }
+}
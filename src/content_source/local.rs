@@ -0,0 +1,183 @@
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use async_trait::async_trait;
+use sha2::{Digest, Sha256};
+use sqlx::types::chrono::{DateTime, Utc};
+
+use super::{get_post_name, get_stable_id, ContentSource};
+use crate::{
+    error::Error,
+    github::{FileMetadata, Repo},
+    AppState,
+};
+
+/// Reads the projects and blog posts from directories on this machine, so the
+/// site can be worked on without a network connection or any credentials.
+pub(crate) struct LocalSource {
+    projects_dir: PathBuf,
+    posts_dir: PathBuf,
+}
+
+impl LocalSource {
+    pub(crate) fn new(projects_dir: PathBuf, posts_dir: PathBuf) -> Self {
+        Self {
+            projects_dir,
+            posts_dir,
+        }
+    }
+}
+
+#[async_trait]
+impl ContentSource for LocalSource {
+    /// Every directory in the projects directory is a project named after it.
+    async fn list_projects(&self, _state: &AppState) -> Result<Vec<Repo>, Error> {
+        let mut projects = Vec::new();
+        for (name, path) in read_dir(&self.projects_dir, true).await? {
+            let dir_metadata = metadata(&path).await?;
+            // A directory's own mtime only moves when entries are added or
+            // removed, so edits to the files in it count as well.
+            let mut modified = dir_metadata.modified().ok();
+            for (_, file) in read_dir(&path, false).await? {
+                let file_modified = metadata(&file).await?.modified().ok();
+                modified = modified.max(file_modified);
+            }
+
+            let url = format!("file://{}", path.display());
+            projects.push(Repo {
+                // Directories don't have an id, so renaming one makes it a
+                // new project.
+                id: get_stable_id(&name),
+                // There's no language detection here, so guess from the
+                // manifest. It's what decides whether we look for crates.
+                language: tokio::fs::try_exists(path.join("Cargo.toml"))
+                    .await
+                    .unwrap_or(false)
+                    .then(|| "Rust".to_string()),
+                name,
+                html_url: url.clone(),
+                url,
+                pushed_at: modified.map(to_date_time).unwrap_or_default(),
+                created_at: dir_metadata.created().ok().map(to_date_time),
+                ..Default::default()
+            });
+        }
+
+        Ok(projects)
+    }
+
+    /// Walks the posts directory and returns every markdown file in it. The sha
+    /// is a hash of the file's content, and the date is its mtime.
+    async fn list_posts(&self, _state: &AppState) -> Result<Option<Vec<FileMetadata>>, Error> {
+        if !tokio::fs::try_exists(&self.posts_dir).await.unwrap_or(false) {
+            return Ok(None);
+        }
+
+        let mut posts = Vec::new();
+        let mut dirs = vec![(String::new(), self.posts_dir.clone())];
+        while let Some((prefix, dir)) = dirs.pop() {
+            for (name, path) in read_dir(&dir, false).await? {
+                let relative_path = format!("{}{}", prefix, name);
+                let file_metadata = metadata(&path).await?;
+                if file_metadata.is_dir() {
+                    dirs.push((format!("{}/", relative_path), path));
+                } else if name.ends_with(".md") {
+                    let content = read(&path).await?;
+                    posts.push(FileMetadata {
+                        name: get_post_name(&relative_path),
+                        sha: hex::encode(Sha256::digest(&content)),
+                        path: relative_path,
+                        updated_at: file_metadata.modified().ok().map(to_date_time),
+                    });
+                }
+            }
+        }
+
+        Ok(Some(posts))
+    }
+
+    async fn fetch_file(
+        &self,
+        _state: &AppState,
+        project: Option<&Repo>,
+        path: &str,
+    ) -> Result<Option<String>, Error> {
+        let path = match project {
+            None => self.posts_dir.join(path),
+            Some(project) => self.projects_dir.join(&project.name).join(path),
+        };
+
+        match tokio::fs::read(&path).await {
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(source) => Err(Error::Io {
+                path: path.display().to_string(),
+                source,
+            }),
+            Ok(content) => Ok(Some(String::from_utf8_lossy(&content).to_string())),
+        }
+    }
+
+    async fn list_dirs(
+        &self,
+        _state: &AppState,
+        project: &Repo,
+        path: &str,
+    ) -> Result<Vec<String>, Error> {
+        let dir = self.projects_dir.join(&project.name).join(path);
+        if !tokio::fs::try_exists(&dir).await.unwrap_or(false) {
+            return Ok(Vec::new());
+        }
+
+        Ok(read_dir(&dir, true)
+            .await?
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect())
+    }
+}
+
+/// The entries of `dir`, sorted by name. Hidden entries, like .git, are left
+/// out.
+async fn read_dir(dir: &Path, dirs_only: bool) -> Result<Vec<(String, PathBuf)>, Error> {
+    let to_error = |source| Error::Io {
+        path: dir.display().to_string(),
+        source,
+    };
+
+    let mut entries = Vec::new();
+    let mut read_dir = tokio::fs::read_dir(dir).await.map_err(to_error)?;
+    while let Some(entry) = read_dir.next_entry().await.map_err(to_error)? {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.starts_with('.') {
+            continue;
+        }
+        if dirs_only && !entry.file_type().await.map_err(to_error)?.is_dir() {
+            continue;
+        }
+        entries.push((name, entry.path()));
+    }
+
+    entries.sort();
+    Ok(entries)
+}
+
+async fn metadata(path: &Path) -> Result<std::fs::Metadata, Error> {
+    tokio::fs::metadata(path).await.map_err(|source| Error::Io {
+        path: path.display().to_string(),
+        source,
+    })
+}
+
+async fn read(path: &Path) -> Result<Vec<u8>, Error> {
+    tokio::fs::read(path).await.map_err(|source| Error::Io {
+        path: path.display().to_string(),
+        source,
+    })
+}
+
+fn to_date_time(time: SystemTime) -> DateTime<Utc> {
+    DateTime::<Utc>::from(time)
+}
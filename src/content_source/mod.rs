@@ -0,0 +1,131 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+use crate::{
+    config::{Config, ContentSourceConfig},
+    error::Error,
+    github::{
+        commits::Commit, languages::LanguageUsage, releases::Release, source::GitHubSource,
+        FileMetadata, Repo,
+    },
+    AppState,
+};
+use git::GitPostsSource;
+use gitlab::GitLabSource;
+use local::LocalSource;
+
+mod git;
+mod gitlab;
+mod local;
+
+/// Where the projects and blog posts are read from. The sync only talks to the
+/// source through this, so it works the same whichever one is configured.
+#[async_trait]
+pub(crate) trait ContentSource: Send + Sync {
+    /// Every project the source has. The one the blog posts live in isn't a
+    /// project, so it's left out.
+    async fn list_projects(&self, state: &AppState) -> Result<Vec<Repo>, Error>;
+
+    /// Every markdown blog post, or `None` if the source has nowhere to keep
+    /// them. `None` leaves the stored posts alone rather than deleting them.
+    async fn list_posts(&self, state: &AppState) -> Result<Option<Vec<FileMetadata>>, Error>;
+
+    /// Reads a file from a project, or from wherever the blog posts are kept
+    /// when `project` is `None`. A missing file is `None`.
+    async fn fetch_file(
+        &self,
+        state: &AppState,
+        project: Option<&Repo>,
+        path: &str,
+    ) -> Result<Option<String>, Error>;
+
+    /// Reads one of the blog posts `list_posts` returned.
+    async fn fetch_post(
+        &self,
+        state: &AppState,
+        post: &FileMetadata,
+    ) -> Result<Option<String>, Error> {
+        self.fetch_file(state, None, &post.path).await
+    }
+
+    /// The names of the directories directly inside `path` in a project.
+    async fn list_dirs(
+        &self,
+        state: &AppState,
+        project: &Repo,
+        path: &str,
+    ) -> Result<Vec<String>, Error>;
+
+    /// How much of the project is in each language, biggest first.
+    async fn list_languages(
+        &self,
+        _state: &AppState,
+        _project: &Repo,
+    ) -> Result<Vec<LanguageUsage>, Error> {
+        Ok(Vec::new())
+    }
+
+    /// The project's most recent releases, newest first.
+    async fn list_releases(&self, _state: &AppState, _project: &Repo) -> Result<Vec<Release>, Error> {
+        Ok(Vec::new())
+    }
+
+    /// The project's most recent commits, newest first.
+    async fn list_commits(&self, _state: &AppState, _project: &Repo) -> Result<Vec<Commit>, Error> {
+        Ok(Vec::new())
+    }
+}
+
+pub(crate) fn from_config(
+    config: &Config,
+) -> Result<Arc<dyn ContentSource>, Box<dyn std::error::Error>> {
+    let source: Arc<dyn ContentSource> = match &config.content_source {
+        ContentSourceConfig::GitHub => Arc::new(GitHubSource),
+        ContentSourceConfig::Local {
+            projects_dir,
+            posts_dir,
+        } => Arc::new(LocalSource::new(projects_dir.clone(), posts_dir.clone())),
+        ContentSourceConfig::GitLab {
+            api_url,
+            namespace,
+            content_project,
+            token,
+        } => Arc::new(GitLabSource::new(
+            api_url.clone(),
+            namespace.clone(),
+            content_project.clone(),
+            token.clone(),
+        )?),
+    };
+
+    // A local git repo for the posts overrides wherever they'd otherwise come
+    // from. The projects still come from the configured source.
+    Ok(match &config.posts_git_repo {
+        None => source,
+        Some(repo_path) => Arc::new(GitPostsSource::new(
+            source,
+            repo_path.clone(),
+            config.posts_git_ref.clone(),
+        )),
+    })
+}
+
+/// The file name of a post without its folders or ".md" extension.
+pub(crate) fn get_post_name(path: &str) -> String {
+    path.rsplit('/')
+        .next()
+        .unwrap_or(path)
+        .trim_end_matches(".md")
+        .to_string()
+}
+
+/// A stable id for something the source doesn't give one to. This is the
+/// 64 bit FNV-1a hash of the key, which, unlike std's hasher, won't change
+/// between builds.
+pub(crate) fn get_stable_id(key: &str) -> i64 {
+    let hash = key.bytes().fold(0xcbf29ce484222325_u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    });
+    hash as i64
+}
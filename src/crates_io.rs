@@ -0,0 +1,202 @@
+use std::{collections::HashMap, time::Duration};
+
+use reqwest::{Client, ClientBuilder, StatusCode};
+use serde_derive::Deserialize;
+use sqlx::FromRow;
+
+use crate::{config::Config, error::Error, github::Repo, AppState};
+
+/// crates.io asks crawlers to make no more than one request a second.
+const REQUEST_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The HTTP client for the crates.io API.
+pub(crate) struct CratesIoClient {
+    client: Client,
+    api_url: String,
+}
+
+/// What crates.io knows about one of our crates.
+#[derive(Clone, Debug, Default, FromRow)]
+pub(crate) struct CrateStats {
+    pub(crate) name: String,
+    pub(crate) repo_id: i64,
+    pub(crate) latest_version: String,
+    pub(crate) downloads: i64,
+    /// Downloads in the last 90 days.
+    pub(crate) recent_downloads: Option<i64>,
+    /// The MSRV of the latest version, if it declares one.
+    pub(crate) rust_version: Option<String>,
+    pub(crate) edition: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct CrateResponse {
+    #[serde(rename = "crate")]
+    krate: CrateInfo,
+    #[serde(default)]
+    versions: Vec<VersionInfo>,
+}
+
+#[derive(Deserialize)]
+struct CrateInfo {
+    name: String,
+    max_stable_version: Option<String>,
+    max_version: String,
+    downloads: i64,
+    recent_downloads: Option<i64>,
+}
+
+#[derive(Deserialize)]
+struct VersionInfo {
+    num: String,
+    rust_version: Option<String>,
+    edition: Option<String>,
+}
+
+impl CratesIoClient {
+    pub(crate) fn new(config: &Config) -> Result<Self, Box<dyn std::error::Error>> {
+        let client = ClientBuilder::new()
+            .danger_accept_invalid_certs(crate::ACCEPT_INVALID_CERTS)
+            .timeout(Duration::from_secs(5))
+            // crates.io turns away requests without a user agent.
+            .user_agent("adamfortune.com server")
+            .build()?;
+
+        Ok(Self {
+            client,
+            api_url: config.crates_io_api_url.clone(),
+        })
+    }
+
+    /// Gets the crate's stats, or `None` if crates.io doesn't have it.
+    async fn get_crate(&self, name: &str, repo_id: i64) -> Result<Option<CrateStats>, Error> {
+        let url = format!("{}api/v1/crates/{}", self.api_url, name);
+        let response = match self.client.get(&url).send().await {
+            Err(err) => {
+                return Err(Error::Upstream {
+                    service: "crates.io",
+                    url,
+                    message: err.to_string(),
+                })
+            }
+            Ok(response) => response,
+        };
+
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !response.status().is_success() {
+            return Err(Error::Upstream {
+                service: "crates.io",
+                message: response.status().to_string(),
+                url,
+            });
+        }
+
+        let response = match response.json::<CrateResponse>().await {
+            Err(err) => {
+                return Err(Error::Upstream {
+                    service: "crates.io",
+                    url,
+                    message: err.to_string(),
+                })
+            }
+            Ok(response) => response,
+        };
+
+        let latest_version = response
+            .krate
+            .max_stable_version
+            .unwrap_or(response.krate.max_version);
+        let version = response
+            .versions
+            .into_iter()
+            .find(|version| version.num == latest_version);
+
+        Ok(Some(CrateStats {
+            name: response.krate.name,
+            repo_id,
+            latest_version,
+            downloads: response.krate.downloads,
+            recent_downloads: response.krate.recent_downloads,
+            rust_version: version
+                .as_ref()
+                .and_then(|version| version.rust_version.clone()),
+            edition: version.and_then(|version| version.edition),
+        }))
+    }
+}
+
+/// Refreshes the stats of every crate found in the showcased repos. Download
+/// counts change all the time, so this runs on every sync rather than only
+/// for the repos that changed.
+pub(crate) async fn sync_crates(state: &AppState) -> Result<(), Error> {
+    let repos = sqlx::query_as::<_, (i64, Vec<String>)>(
+        "SELECT id, crate_names FROM MrAdultRepositories WHERE cardinality(crate_names) > 0;",
+    )
+    .fetch_all(&state.db_connection)
+    .await?;
+
+    let crate_names = repos.into_iter().flat_map(|(repo_id, crate_names)| {
+        crate_names
+            .into_iter()
+            .map(move |crate_name| (repo_id, crate_name))
+    });
+
+    let mut crates = Vec::new();
+    for (index, (repo_id, crate_name)) in crate_names.enumerate() {
+        if index > 0 {
+            tokio::time::sleep(REQUEST_INTERVAL).await;
+        }
+        match state.crates_io_client.get_crate(&crate_name, repo_id).await? {
+            None => println!("{} isn't on crates.io", crate_name),
+            Some(stats) => crates.push(stats),
+        }
+    }
+
+    let mut transaction = state.db_connection.begin().await?;
+    sqlx::query("DELETE FROM Crates;")
+        .execute(&mut *transaction)
+        .await?;
+    for stats in crates {
+        sqlx::query(
+            r#"INSERT INTO Crates( name, repo_id, latest_version, downloads, recent_downloads, rust_version, edition )
+            VALUES ( $1, $2, $3, $4, $5, $6, $7 )
+            ON CONFLICT (name) DO NOTHING;"#,
+        )
+        .bind(stats.name)
+        .bind(stats.repo_id)
+        .bind(stats.latest_version)
+        .bind(stats.downloads)
+        .bind(stats.recent_downloads)
+        .bind(stats.rust_version)
+        .bind(stats.edition)
+        .execute(&mut *transaction)
+        .await?;
+    }
+    transaction.commit().await?;
+
+    Ok(())
+}
+
+/// Fills in the crate stats of each of the repos from the DB.
+pub(crate) async fn load_crates(state: &AppState, repos: &mut [Repo]) -> Result<(), Error> {
+    let repo_ids = repos.iter().map(|repo| repo.id).collect::<Vec<_>>();
+    let rows = sqlx::query_as::<_, CrateStats>(
+        "SELECT * FROM Crates WHERE repo_id = ANY($1) ORDER BY name;",
+    )
+    .bind(repo_ids)
+    .fetch_all(&state.db_connection)
+    .await?;
+
+    let mut crates_by_repo: HashMap<i64, Vec<CrateStats>> = HashMap::new();
+    for row in rows {
+        crates_by_repo.entry(row.repo_id).or_default().push(row);
+    }
+
+    for repo in repos {
+        repo.crates = crates_by_repo.remove(&repo.id).unwrap_or_default();
+    }
+
+    Ok(())
+}
@@ -0,0 +1,179 @@
+use std::fmt::{Display, Formatter};
+
+use axum::{
+    response::{IntoResponse, Response},
+    Json,
+};
+use http::{
+    header::{CONTENT_TYPE, RETRY_AFTER},
+    HeaderValue, StatusCode,
+};
+use serde_derive::Serialize;
+use sqlx::types::chrono::{DateTime, Utc};
+
+/// Everything that can go wrong reading from the DB or syncing with the
+/// content source.
+#[derive(Debug)]
+pub(crate) enum Error {
+    /// A query against Postgres failed.
+    Db(sqlx::Error),
+    /// We couldn't get a response out of GitHub at all.
+    GitHubTransport { url: String, source: reqwest::Error },
+    /// GitHub answered with an error status.
+    GitHubStatus {
+        url: String,
+        status: reqwest::StatusCode,
+    },
+    /// We've used up our GitHub requests until `reset_at`.
+    GitHubRateLimited { reset_at: DateTime<Utc> },
+    /// We couldn't sign the JWT for the GitHub App.
+    GitHubAuth(jsonwebtoken::errors::Error),
+    /// A service other than GitHub, like crates.io or GitLab, couldn't be
+    /// reached, answered with an error status or sent something we couldn't
+    /// read.
+    Upstream {
+        service: &'static str,
+        url: String,
+        message: String,
+    },
+    /// Reading from the local content directories failed.
+    Io {
+        path: String,
+        source: std::io::Error,
+    },
+    /// Reading from a local git repo failed.
+    Git { path: String, message: String },
+    /// A response didn't have the shape we expected.
+    Decode { url: String, message: String },
+    /// The thing that was asked for doesn't exist.
+    NotFound(String),
+    /// The request was missing credentials, or had the wrong ones.
+    Unauthorized(String),
+    /// The request itself was malformed, like an unknown query parameter value.
+    BadRequest(String),
+    /// The sync lock's connection went away mid-sync, so the sync was
+    /// abandoned before another replica's could clash with it.
+    SyncLockLost,
+}
+
+impl Error {
+    fn status(&self) -> StatusCode {
+        match self {
+            Error::Db(_) => StatusCode::SERVICE_UNAVAILABLE,
+            Error::GitHubRateLimited { .. } | Error::SyncLockLost => {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+            Error::GitHubTransport { .. }
+            | Error::GitHubStatus { .. }
+            | Error::GitHubAuth(_)
+            | Error::Upstream { .. }
+            | Error::Decode { .. } => StatusCode::BAD_GATEWAY,
+            Error::Io { .. } | Error::Git { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            Error::NotFound(_) => StatusCode::NOT_FOUND,
+            Error::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            Error::BadRequest(_) => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    fn title(&self) -> &'static str {
+        match self {
+            Error::Db(_) => "The database is unavailable",
+            Error::GitHubTransport { .. } => "GitHub could not be reached",
+            Error::GitHubStatus { .. } => "GitHub returned an error",
+            Error::GitHubRateLimited { .. } => "The GitHub rate limit has been reached",
+            Error::GitHubAuth(_) => "Authenticating with GitHub failed",
+            Error::Upstream { .. } => "An upstream service returned an error",
+            Error::Io { .. } => "Reading the local content failed",
+            Error::Git { .. } => "Reading the local git repository failed",
+            Error::Decode { .. } => "The content source returned an unexpected response",
+            Error::NotFound(_) => "Not found",
+            Error::Unauthorized(_) => "Unauthorized",
+            Error::BadRequest(_) => "Bad request",
+            Error::SyncLockLost => "The sync was interrupted",
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Db(err) => write!(f, "Database error: {}", err),
+            Error::GitHubTransport { url, source } => {
+                write!(f, "Request to {} failed: {}", url, source)
+            }
+            Error::GitHubStatus { url, status } => write!(f, "{} returned {}", url, status),
+            Error::GitHubRateLimited { reset_at } => {
+                write!(f, "GitHub rate limit reached. It resets at {}", reset_at)
+            }
+            Error::GitHubAuth(err) => write!(f, "Failed to sign the GitHub App JWT: {}", err),
+            Error::Upstream {
+                service,
+                url,
+                message,
+            } => write!(f, "Request to {} at {} failed: {}", service, url, message),
+            Error::Io { path, source } => write!(f, "Failed to read {}: {}", path, source),
+            Error::Git { path, message } => {
+                write!(f, "Failed to read the git repo at {}: {}", path, message)
+            }
+            Error::Decode { url, message } => {
+                write!(f, "Failed to decode the response from {}: {}", url, message)
+            }
+            Error::NotFound(what) => write!(f, "{} was not found", what),
+            Error::Unauthorized(reason) => write!(f, "{}", reason),
+            Error::BadRequest(reason) => write!(f, "{}", reason),
+            Error::SyncLockLost => write!(f, "Lost the sync lock, so the sync was abandoned"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<sqlx::Error> for Error {
+    fn from(value: sqlx::Error) -> Self {
+        Error::Db(value)
+    }
+}
+
+/// An RFC 7807 problem details body.
+#[derive(Serialize)]
+struct ProblemDetails {
+    r#type: &'static str,
+    title: &'static str,
+    status: u16,
+    detail: String,
+}
+
+impl IntoResponse for Error {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        let detail = match &self {
+            // Don't hand the details of our infrastructure to visitors.
+            Error::Db(_) | Error::Io { .. } | Error::Git { .. } => {
+                crate::utils::log_error(&self);
+                "Try again later.".to_string()
+            }
+            _ => self.to_string(),
+        };
+
+        let body = ProblemDetails {
+            r#type: "about:blank",
+            title: self.title(),
+            status: status.as_u16(),
+            detail,
+        };
+
+        let mut response = (status, Json(body)).into_response();
+        response.headers_mut().insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static("application/problem+json"),
+        );
+        if let Error::GitHubRateLimited { reset_at } = self {
+            let retry_after = (reset_at - Utc::now()).num_seconds().max(0);
+            response
+                .headers_mut()
+                .insert(RETRY_AFTER, HeaderValue::from(retry_after));
+        }
+
+        response
+    }
+}
@@ -0,0 +1,184 @@
+use reqwest::{
+    header::{
+        HeaderMap, HeaderName, HeaderValue, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED,
+        LINK,
+    },
+    Response, StatusCode,
+};
+use sqlx::{
+    types::chrono::{DateTime, Utc},
+    FromRow,
+};
+
+use super::client::GitHubClient;
+use crate::{error::Error, utils::log_error, AppState};
+
+/// A response body from GitHub, either freshly downloaded or replayed from
+/// GitHubResponseCache because GitHub told us it hasn't changed.
+pub(crate) struct GitHubResponse {
+    pub(crate) body: String,
+    /// The `Link` header, used for pagination.
+    pub(crate) link: Option<String>,
+}
+
+#[derive(FromRow)]
+struct CachedResponse {
+    #[allow(unused)]
+    url: String,
+    etag: Option<String>,
+    last_modified: Option<String>,
+    link: Option<String>,
+    body: String,
+    #[allow(unused)]
+    updated_at: DateTime<Utc>,
+}
+
+/// Sends a GET request, conditional on the validators from the last time we
+/// downloaded this URL. A 304 means the resource is unchanged, so the cached
+/// body is returned instead. 304s don't count against GitHub's rate limit.
+/// A 404 comes back as `None`.
+///
+/// Every use of an entry bumps its `updated_at`, which is what [`prune`] goes
+/// by. Dry runs only read the cache.
+pub(crate) async fn conditional_get(
+    state: &AppState,
+    client: &GitHubClient,
+    url: &str,
+) -> Result<Option<GitHubResponse>, Error> {
+    let cached = match sqlx::query_as::<_, CachedResponse>(
+        "SELECT * FROM GitHubResponseCache WHERE url=$1 LIMIT 1;",
+    )
+    .bind(url)
+    .fetch_optional(&state.db_connection)
+    .await
+    {
+        Err(err) => {
+            // The cache is only an optimization, so carry on without it.
+            log_error(err);
+            None
+        }
+        Ok(cached) => cached,
+    };
+
+    let mut headers = HeaderMap::new();
+    if let Some(cached) = &cached {
+        let etag = cached
+            .etag
+            .as_deref()
+            .and_then(|etag| HeaderValue::from_str(etag).ok());
+        if let Some(etag) = etag {
+            headers.insert(IF_NONE_MATCH, etag);
+        }
+        let last_modified = cached
+            .last_modified
+            .as_deref()
+            .and_then(|last_modified| HeaderValue::from_str(last_modified).ok());
+        if let Some(last_modified) = last_modified {
+            headers.insert(IF_MODIFIED_SINCE, last_modified);
+        }
+    }
+
+    let response = client.get(url, headers).await?;
+
+    if response.status() == StatusCode::NOT_MODIFIED {
+        if cached.is_some() && !state.dry_run {
+            let result = sqlx::query(
+                "UPDATE GitHubResponseCache SET updated_at = NOW() WHERE url=$1;",
+            )
+            .bind(url)
+            .execute(&state.db_connection)
+            .await;
+            if let Err(err) = result {
+                log_error(err);
+            }
+        }
+
+        return match cached {
+            Some(cached) => Ok(Some(GitHubResponse {
+                body: cached.body,
+                link: cached.link,
+            })),
+            // We only send validators we have a cached body for, so this
+            // shouldn't happen.
+            None => Err(Error::GitHubStatus {
+                url: url.to_string(),
+                status: StatusCode::NOT_MODIFIED,
+            }),
+        };
+    }
+
+    if response.status() == StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+
+    if !response.status().is_success() {
+        return Err(Error::GitHubStatus {
+            url: url.to_string(),
+            status: response.status(),
+        });
+    }
+
+    let etag = get_header(&response, ETAG);
+    let last_modified = get_header(&response, LAST_MODIFIED);
+    let link = get_header(&response, LINK);
+
+    let body = match response.text().await {
+        Err(source) => {
+            return Err(Error::GitHubTransport {
+                url: url.to_string(),
+                source,
+            })
+        }
+        Ok(body) => body,
+    };
+
+    if (etag.is_some() || last_modified.is_some()) && !state.dry_run {
+        let result = sqlx::query(
+            r#"INSERT INTO GitHubResponseCache( url, etag, last_modified, link, body, updated_at )
+            VALUES ( $1, $2, $3, $4, $5, NOW() )
+            ON CONFLICT (url) DO
+            UPDATE SET
+                etag = EXCLUDED.etag,
+                last_modified = EXCLUDED.last_modified,
+                link = EXCLUDED.link,
+                body = EXCLUDED.body,
+                updated_at = EXCLUDED.updated_at;"#,
+        )
+        .bind(url)
+        .bind(&etag)
+        .bind(&last_modified)
+        .bind(&link)
+        .bind(&body)
+        .execute(&state.db_connection)
+        .await;
+
+        if let Err(err) = result {
+            log_error(err);
+        }
+    }
+
+    Ok(Some(GitHubResponse { body, link }))
+}
+
+/// Deletes the responses nobody has used since `since`. Run after a successful
+/// sync, with the time it started, this drops the entries of deleted repos and
+/// posts. Responses only fetched for changed repos, like READMEs, go as well,
+/// and are downloaded in full the next time they're needed.
+pub(crate) async fn prune(state: &AppState, since: DateTime<Utc>) {
+    let result = sqlx::query("DELETE FROM GitHubResponseCache WHERE updated_at < $1;")
+        .bind(since)
+        .execute(&state.db_connection)
+        .await;
+
+    if let Err(err) = result {
+        log_error(err);
+    }
+}
+
+fn get_header(response: &Response, name: HeaderName) -> Option<String> {
+    response
+        .headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_string())
+}
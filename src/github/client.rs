@@ -0,0 +1,259 @@
+use std::{sync::Mutex, time::Duration};
+
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use reqwest::{
+    header::{HeaderMap, ACCEPT, AUTHORIZATION},
+    Client, ClientBuilder, Response, StatusCode,
+};
+use serde_derive::{Deserialize, Serialize};
+use sqlx::types::chrono::{DateTime, TimeZone, Utc};
+
+use crate::{
+    config::{Config, GitHubAuth},
+    error::Error,
+};
+
+const RATE_LIMIT_REMAINING_HEADER: &str = "X-RateLimit-Remaining";
+const RATE_LIMIT_RESET_HEADER: &str = "X-RateLimit-Reset";
+const RETRY_AFTER_HEADER: &str = "Retry-After";
+
+/// The HTTP client every GitHub call goes through. It keeps track of the rate
+/// limit GitHub reports on each response, and refuses to send anything once
+/// the limit is used up so a sync fails fast instead of failing piece by piece.
+/// It also attaches our credentials, if we have any, to every request.
+pub(crate) struct GitHubClient {
+    client: Client,
+    api_url: String,
+    credentials: Credentials,
+    rate_limit: Mutex<RateLimit>,
+}
+
+enum Credentials {
+    Anonymous,
+    Token(String),
+    App {
+        app_id: String,
+        key: EncodingKey,
+        installation_id: u64,
+        /// Installation tokens last an hour, so we reuse one until it's close
+        /// to expiring.
+        installation_token: tokio::sync::Mutex<Option<InstallationToken>>,
+    },
+}
+
+#[derive(Deserialize)]
+struct InstallationToken {
+    token: String,
+    expires_at: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+struct AppClaims {
+    iat: i64,
+    exp: i64,
+    iss: String,
+}
+
+#[derive(Clone, Copy, Default)]
+struct RateLimit {
+    remaining: Option<u32>,
+    reset_at: Option<DateTime<Utc>>,
+}
+
+impl GitHubClient {
+    pub(crate) fn new(config: &Config) -> Result<Self, Box<dyn std::error::Error>> {
+        let client = ClientBuilder::new()
+            .danger_accept_invalid_certs(crate::ACCEPT_INVALID_CERTS)
+            .timeout(Duration::from_secs(5))
+            .user_agent("adamfortune.com server")
+            .build()?;
+
+        let credentials = match &config.github_auth {
+            GitHubAuth::Anonymous => Credentials::Anonymous,
+            GitHubAuth::Token(token) => Credentials::Token(token.clone()),
+            GitHubAuth::App {
+                app_id,
+                private_key_pem,
+                installation_id,
+            } => Credentials::App {
+                app_id: app_id.clone(),
+                key: EncodingKey::from_rsa_pem(private_key_pem.as_bytes())?,
+                installation_id: *installation_id,
+                installation_token: tokio::sync::Mutex::new(None),
+            },
+        };
+
+        Ok(Self {
+            client,
+            api_url: config.github_api_url.clone(),
+            credentials,
+            rate_limit: Mutex::new(RateLimit::default()),
+        })
+    }
+
+    /// Whether we're authenticated as a GitHub App installation, as opposed to
+    /// a user.
+    pub(crate) fn is_app_installation(&self) -> bool {
+        matches!(self.credentials, Credentials::App { .. })
+    }
+
+    /// Sends a GET request, unless we already know we're rate limited. Rate
+    /// limit responses (403 or 429) are turned into errors. Every other
+    /// status is returned for the caller to handle.
+    pub(crate) async fn get(&self, url: &str, headers: HeaderMap) -> Result<Response, Error> {
+        if let Some(reset_at) = self.rate_limited_until() {
+            return Err(Error::GitHubRateLimited { reset_at });
+        }
+
+        let mut request = self.client.get(url).headers(headers);
+        if let Some(authorization) = self.get_authorization().await? {
+            request = request.header(AUTHORIZATION, authorization);
+        }
+
+        let response = match request.send().await {
+            Err(source) => {
+                return Err(Error::GitHubTransport {
+                    url: url.to_string(),
+                    source,
+                })
+            }
+            Ok(response) => response,
+        };
+
+        let remaining = get_header::<u32>(&response, RATE_LIMIT_REMAINING_HEADER);
+        let reset_at = get_header::<i64>(&response, RATE_LIMIT_RESET_HEADER)
+            .and_then(|seconds| Utc.timestamp_opt(seconds, 0).single());
+        // Secondary rate limits come with a Retry-After instead of a reset time.
+        let retry_at = get_header::<i64>(&response, RETRY_AFTER_HEADER)
+            .map(|seconds| Utc::now() + chrono::Duration::seconds(seconds));
+
+        let status = response.status();
+        let is_rate_limited = status == StatusCode::TOO_MANY_REQUESTS
+            || (status == StatusCode::FORBIDDEN && (remaining == Some(0) || retry_at.is_some()));
+
+        let mut rate_limit = self
+            .rate_limit
+            .lock()
+            .expect("rate limit lock to not be poisoned");
+        if is_rate_limited {
+            let reset_at = retry_at
+                .or(reset_at)
+                .unwrap_or_else(|| Utc::now() + chrono::Duration::minutes(1));
+            println!("GitHub rate limit hit. Pausing until {}", reset_at);
+            *rate_limit = RateLimit {
+                remaining: Some(0),
+                reset_at: Some(reset_at),
+            };
+            return Err(Error::GitHubRateLimited { reset_at });
+        }
+
+        if remaining.is_some() {
+            *rate_limit = RateLimit {
+                remaining,
+                reset_at,
+            };
+        }
+
+        Ok(response)
+    }
+
+    /// When we've used up our requests, the time GitHub will let us make more.
+    pub(crate) fn rate_limited_until(&self) -> Option<DateTime<Utc>> {
+        let rate_limit = *self
+            .rate_limit
+            .lock()
+            .expect("rate limit lock to not be poisoned");
+        match rate_limit {
+            RateLimit {
+                remaining: Some(0),
+                reset_at: Some(reset_at),
+            } if reset_at > Utc::now() => Some(reset_at),
+            _ => None,
+        }
+    }
+
+    /// The Authorization header value for the configured credentials.
+    async fn get_authorization(&self) -> Result<Option<String>, Error> {
+        match &self.credentials {
+            Credentials::Anonymous => Ok(None),
+            Credentials::Token(token) => Ok(Some(format!("Bearer {}", token))),
+            Credentials::App {
+                app_id,
+                key,
+                installation_id,
+                installation_token,
+            } => {
+                let mut installation_token = installation_token.lock().await;
+                if let Some(token) = installation_token.as_ref() {
+                    if token.expires_at - chrono::Duration::minutes(5) > Utc::now() {
+                        return Ok(Some(format!("Bearer {}", token.token)));
+                    }
+                }
+
+                let token = self
+                    .create_installation_token(app_id, key, *installation_id)
+                    .await?;
+                let authorization = format!("Bearer {}", token.token);
+                *installation_token = Some(token);
+                Ok(Some(authorization))
+            }
+        }
+    }
+
+    /// Signs a JWT as the app and trades it for an installation token.
+    async fn create_installation_token(
+        &self,
+        app_id: &str,
+        key: &EncodingKey,
+        installation_id: u64,
+    ) -> Result<InstallationToken, Error> {
+        let now = Utc::now().timestamp();
+        let claims = AppClaims {
+            // Back-date the token a little in case our clock is ahead of GitHub's.
+            iat: now - 60,
+            // GitHub rejects app JWTs that live longer than 10 minutes.
+            exp: now + 9 * 60,
+            iss: app_id.to_string(),
+        };
+
+        let jwt = jsonwebtoken::encode(&Header::new(Algorithm::RS256), &claims, key)
+            .map_err(Error::GitHubAuth)?;
+
+        let mut url = self.api_url.clone();
+        url.push_str(&format!(
+            "app/installations/{}/access_tokens",
+            installation_id
+        ));
+
+        let response = match self
+            .client
+            .post(&url)
+            .header(AUTHORIZATION, format!("Bearer {}", jwt))
+            .header(ACCEPT, "application/vnd.github+json")
+            .send()
+            .await
+        {
+            Err(source) => return Err(Error::GitHubTransport { url, source }),
+            Ok(response) => response,
+        };
+
+        if !response.status().is_success() {
+            return Err(Error::GitHubStatus {
+                url,
+                status: response.status(),
+            });
+        }
+
+        response
+            .json::<InstallationToken>()
+            .await
+            .map_err(|err| Error::Decode {
+                url,
+                message: err.to_string(),
+            })
+    }
+}
+
+fn get_header<T: std::str::FromStr>(response: &Response, name: &str) -> Option<T> {
+    response.headers().get(name)?.to_str().ok()?.parse().ok()
+}
@@ -0,0 +1,152 @@
+use serde_derive::Deserialize;
+use sqlx::{
+    types::chrono::{DateTime, Utc},
+    FromRow, Postgres, Transaction,
+};
+
+use super::{cache, client::GitHubClient, decode, Repo};
+use crate::{error::Error, AppState};
+
+#[derive(Clone, Debug, Default, FromRow)]
+pub(crate) struct Commit {
+    pub(crate) sha: String,
+    /// The first line of the commit message.
+    pub(crate) headline: String,
+    /// The author's GitHub login, or the name from the commit if GitHub
+    /// couldn't match it to an account.
+    pub(crate) author: String,
+    pub(crate) committed_at: DateTime<Utc>,
+    pub(crate) html_url: String,
+    /// Only filled in when reading commits back out of the DB.
+    #[sqlx(default)]
+    pub(crate) repo_name: String,
+}
+
+#[derive(Deserialize)]
+struct GitHubCommit {
+    sha: String,
+    html_url: String,
+    commit: GitHubCommitDetails,
+    author: Option<GitHubUser>,
+}
+
+#[derive(Deserialize)]
+struct GitHubCommitDetails {
+    message: String,
+    author: GitHubCommitAuthor,
+}
+
+#[derive(Deserialize)]
+struct GitHubCommitAuthor {
+    name: String,
+    date: DateTime<Utc>,
+}
+
+#[derive(Deserialize)]
+struct GitHubUser {
+    login: String,
+}
+
+/// Gets the most recent commits on the repo's default branch, newest first.
+pub(crate) async fn get_github_commits(
+    state: &AppState,
+    client: &GitHubClient,
+    repo: &Repo,
+) -> Result<Vec<Commit>, Error> {
+    let mut get_commits_url = state.config.github_api_url.clone();
+    get_commits_url.push_str(&format!(
+        "repos/{}/{}/commits?per_page={}",
+        state.config.github_owner, &repo.name, state.config.github_commits_per_repo
+    ));
+
+    // Empty repos answer with a 409, which isn't worth failing the sync over.
+    let response = match cache::conditional_get(state, client, &get_commits_url).await {
+        Err(Error::GitHubStatus { status, .. }) if status == reqwest::StatusCode::CONFLICT => {
+            return Ok(Vec::new())
+        }
+        Err(err) => return Err(err),
+        Ok(None) => return Ok(Vec::new()),
+        Ok(Some(response)) => response,
+    };
+
+    let commits = decode::<Vec<GitHubCommit>>(&get_commits_url, &response.body)?;
+    Ok(commits
+        .into_iter()
+        .map(|commit| Commit {
+            sha: commit.sha,
+            headline: commit
+                .commit
+                .message
+                .lines()
+                .next()
+                .unwrap_or_default()
+                .to_string(),
+            author: match commit.author {
+                None => commit.commit.author.name,
+                Some(user) => user.login,
+            },
+            committed_at: commit.commit.author.date,
+            html_url: commit.html_url,
+            repo_name: repo.name.clone(),
+        })
+        .collect())
+}
+
+/// Swaps the stored commits of a repo for the ones just fetched.
+pub(crate) async fn replace_commits(
+    transaction: &mut Transaction<'_, Postgres>,
+    repo_id: i64,
+    commits: &[Commit],
+) -> Result<(), Error> {
+    sqlx::query("DELETE FROM Commits WHERE repo_id=$1;")
+        .bind(repo_id)
+        .execute(&mut **transaction)
+        .await?;
+
+    for commit in commits {
+        sqlx::query(
+            r#"INSERT INTO Commits( repo_id, sha, headline, author, committed_at, html_url )
+            VALUES ( $1, $2, $3, $4, $5, $6 );"#,
+        )
+        .bind(repo_id)
+        .bind(&commit.sha)
+        .bind(&commit.headline)
+        .bind(&commit.author)
+        .bind(commit.committed_at)
+        .bind(&commit.html_url)
+        .execute(&mut **transaction)
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// The stored commits of a repo, newest first.
+pub(crate) async fn get_commits(state: &AppState, repo_id: i64) -> Result<Vec<Commit>, Error> {
+    let commits = sqlx::query_as::<_, Commit>(
+        r#"SELECT Commits.*, MrAdultRepositories.name AS repo_name
+        FROM Commits JOIN MrAdultRepositories ON MrAdultRepositories.id = Commits.repo_id
+        WHERE repo_id=$1
+        ORDER BY committed_at DESC;"#,
+    )
+    .bind(repo_id)
+    .fetch_all(&state.db_connection)
+    .await?;
+
+    Ok(commits)
+}
+
+/// The newest commits across every showcased repo.
+pub(crate) async fn get_recent_activity(state: &AppState, limit: i64) -> Result<Vec<Commit>, Error> {
+    let commits = sqlx::query_as::<_, Commit>(
+        r#"SELECT Commits.*, MrAdultRepositories.name AS repo_name
+        FROM Commits JOIN MrAdultRepositories ON MrAdultRepositories.id = Commits.repo_id
+        ORDER BY committed_at DESC
+        LIMIT $1;"#,
+    )
+    .bind(limit)
+    .fetch_all(&state.db_connection)
+    .await?;
+
+    Ok(commits)
+}
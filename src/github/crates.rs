@@ -0,0 +1,283 @@
+use std::future::Future;
+
+use serde_derive::Deserialize;
+
+use super::Repo;
+use crate::{content_source::ContentSource, error::Error, utils::log_error, AppState};
+
+#[derive(Deserialize)]
+struct CargoManifest {
+    package: Option<Package>,
+    workspace: Option<Workspace>,
+}
+
+#[derive(Deserialize)]
+struct Package {
+    name: String,
+    publish: Option<Publish>,
+}
+
+#[derive(Deserialize)]
+struct Workspace {
+    #[serde(default)]
+    members: Vec<String>,
+    #[serde(default)]
+    exclude: Vec<String>,
+    package: Option<WorkspacePackage>,
+}
+
+#[derive(Deserialize)]
+struct WorkspacePackage {
+    publish: Option<Publish>,
+}
+
+/// `publish` is either a flag, the list of registries the crate may be
+/// published to, or `{ workspace = true }` to use the workspace's setting.
+#[derive(Clone, Deserialize)]
+#[serde(untagged)]
+enum Publish {
+    Flag(bool),
+    Registries(Vec<String>),
+    Inherited { workspace: bool },
+}
+
+/// Reads the repo's Cargo.toml, and those of its workspace members, and returns
+/// the names of the crates that are published to crates.io. Repos without Rust
+/// in them aren't checked.
+pub(crate) async fn detect_crates(
+    state: &AppState,
+    source: &dyn ContentSource,
+    repo: &Repo,
+) -> Result<Vec<String>, Error> {
+    let is_rust = repo.language.as_deref() == Some("Rust")
+        || repo
+            .languages
+            .iter()
+            .any(|language| language.language == "Rust");
+    if !is_rust {
+        return Ok(Vec::new());
+    }
+
+    let root_manifest = match get_manifest(state, source, repo, "").await? {
+        None => return Ok(Vec::new()),
+        Some(manifest) => manifest,
+    };
+
+    let mut crate_names = Vec::new();
+    let workspace_publish = root_manifest
+        .workspace
+        .as_ref()
+        .and_then(|workspace| workspace.package.as_ref())
+        .and_then(|package| package.publish.clone());
+    if let Some(package) = &root_manifest.package {
+        if is_published(package, workspace_publish.as_ref()) {
+            crate_names.push(package.name.clone());
+        }
+    }
+
+    if let Some(workspace) = &root_manifest.workspace {
+        let list_dirs =
+            |parent: String| async move { source.list_dirs(state, repo, &parent).await };
+        for member in get_member_dirs(repo, workspace, list_dirs).await? {
+            let package = get_manifest(state, source, repo, &member)
+                .await?
+                .and_then(|manifest| manifest.package);
+            if let Some(package) = package {
+                if is_published(&package, workspace_publish.as_ref()) {
+                    crate_names.push(package.name);
+                }
+            }
+        }
+    }
+
+    crate_names.sort();
+    crate_names.dedup();
+    Ok(crate_names)
+}
+
+/// Reads the Cargo.toml in `dir`. A manifest we can't parse is logged and
+/// skipped, since it only costs us a link.
+async fn get_manifest(
+    state: &AppState,
+    source: &dyn ContentSource,
+    repo: &Repo,
+    dir: &str,
+) -> Result<Option<CargoManifest>, Error> {
+    let path = if dir.is_empty() {
+        "Cargo.toml".to_string()
+    } else {
+        format!("{}/Cargo.toml", dir.trim_end_matches('/'))
+    };
+
+    let content = match source.fetch_file(state, Some(repo), &path).await? {
+        None => return Ok(None),
+        Some(content) => content,
+    };
+
+    match toml::from_str::<CargoManifest>(&content) {
+        Err(err) => {
+            println!("Failed to parse {} in {}", path, repo.name);
+            log_error(err);
+            Ok(None)
+        }
+        Ok(manifest) => Ok(Some(manifest)),
+    }
+}
+
+/// Expands the workspace members into directories, using `list_dirs` to look
+/// inside a member's parent. Only a trailing `/*` glob is supported, which
+/// covers the usual `crates/*` layout.
+async fn get_member_dirs<F, Fut>(
+    repo: &Repo,
+    workspace: &Workspace,
+    list_dirs: F,
+) -> Result<Vec<String>, Error>
+where
+    F: Fn(String) -> Fut,
+    Fut: Future<Output = Result<Vec<String>, Error>>,
+{
+    let mut dirs = Vec::new();
+    for member in &workspace.members {
+        match member.strip_suffix("/*") {
+            Some(parent) => {
+                for child in list_dirs(parent.to_string()).await? {
+                    dirs.push(format!("{}/{}", parent, child));
+                }
+            }
+            None if member.contains(['*', '?', '[']) => {
+                println!("Skipping unsupported workspace member {} in {}", member, repo.name);
+            }
+            None => dirs.push(member.clone()),
+        }
+    }
+
+    dirs.retain(|dir| {
+        !workspace
+            .exclude
+            .iter()
+            .any(|excluded| excluded.trim_end_matches('/') == dir)
+    });
+    Ok(dirs)
+}
+
+/// Whether the package can be published to crates.io. Cargo's default is yes.
+fn is_published(package: &Package, workspace_publish: Option<&Publish>) -> bool {
+    let publish = match &package.publish {
+        Some(Publish::Inherited { workspace: true }) => workspace_publish,
+        publish => publish.as_ref(),
+    };
+
+    match publish {
+        None | Some(Publish::Inherited { .. }) => true,
+        Some(Publish::Flag(publish)) => *publish,
+        Some(Publish::Registries(registries)) => {
+            registries.iter().any(|registry| registry == "crates-io")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest(toml: &str) -> CargoManifest {
+        toml::from_str(toml).expect("the manifest to parse")
+    }
+
+    /// Whether the root package of `toml` counts as published.
+    fn is_root_published(toml: &str) -> bool {
+        let manifest = manifest(toml);
+        let workspace_publish = manifest
+            .workspace
+            .and_then(|workspace| workspace.package)
+            .and_then(|package| package.publish);
+        is_published(
+            &manifest.package.expect("a package"),
+            workspace_publish.as_ref(),
+        )
+    }
+
+    #[test]
+    fn packages_are_published_by_default() {
+        assert!(is_root_published(
+            r#"
+            [package]
+            name = "a"
+            "#
+        ));
+    }
+
+    #[test]
+    fn publish_false_is_not_published() {
+        assert!(!is_root_published(
+            r#"
+            [package]
+            name = "a"
+            publish = false
+            "#
+        ));
+    }
+
+    #[test]
+    fn publish_registries_have_to_include_crates_io() {
+        assert!(is_root_published(
+            r#"
+            [package]
+            name = "a"
+            publish = ["crates-io"]
+            "#
+        ));
+        assert!(!is_root_published(
+            r#"
+            [package]
+            name = "a"
+            publish = ["my-registry"]
+            "#
+        ));
+    }
+
+    #[test]
+    fn publish_can_be_inherited_from_the_workspace() {
+        assert!(!is_root_published(
+            r#"
+            [package]
+            name = "a"
+            publish.workspace = true
+
+            [workspace.package]
+            publish = false
+            "#
+        ));
+        assert!(is_root_published(
+            r#"
+            [package]
+            name = "a"
+            publish.workspace = true
+
+            [workspace]
+            "#
+        ));
+    }
+
+    #[tokio::test]
+    async fn member_globs_are_expanded_and_excludes_dropped() {
+        let workspace = manifest(
+            r#"
+            [workspace]
+            members = ["crates/*", "cli"]
+            exclude = ["crates/scratch/"]
+            "#,
+        )
+        .workspace
+        .expect("a workspace");
+
+        let list_dirs = |parent: String| async move {
+            assert_eq!(parent, "crates");
+            Ok(vec!["core".to_string(), "scratch".to_string()])
+        };
+        let dirs = get_member_dirs(&Repo::default(), &workspace, list_dirs)
+            .await
+            .expect("the listing to succeed");
+        assert_eq!(dirs, vec!["crates/core", "cli"]);
+    }
+}
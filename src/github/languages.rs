@@ -0,0 +1,124 @@
+use std::collections::{BTreeMap, HashMap};
+
+use sqlx::{FromRow, Postgres, Transaction};
+
+use super::{cache, client::GitHubClient, decode, Repo};
+use crate::{error::Error, AppState};
+
+/// How much of a repo is written in a language. GitHub counts bytes, while
+/// GitLab only gives each language's share.
+#[derive(Clone, Debug, Default, FromRow)]
+pub(crate) struct LanguageUsage {
+    pub(crate) language: String,
+    pub(crate) bytes: Option<i64>,
+    /// The language's share of the repo, from 0 to 100.
+    pub(crate) share: Option<f64>,
+}
+
+#[derive(FromRow)]
+struct RepoLanguage {
+    repo_id: i64,
+    language: String,
+    bytes: Option<i64>,
+    share: Option<f64>,
+}
+
+/// Gets the byte count of every language GitHub detected in the repo, biggest
+/// first.
+pub(crate) async fn get_languages(
+    state: &AppState,
+    client: &GitHubClient,
+    repo: &Repo,
+) -> Result<Vec<LanguageUsage>, Error> {
+    let mut get_languages_url = state.config.github_api_url.clone();
+    get_languages_url.push_str(&format!(
+        "repos/{}/{}/languages",
+        state.config.github_owner, &repo.name
+    ));
+
+    let response = match cache::conditional_get(state, client, &get_languages_url).await? {
+        None => return Ok(Vec::new()),
+        Some(response) => response,
+    };
+
+    let languages = decode::<BTreeMap<String, i64>>(&get_languages_url, &response.body)?;
+    let mut languages = languages
+        .into_iter()
+        .map(|(language, bytes)| LanguageUsage {
+            language,
+            bytes: Some(bytes),
+            share: None,
+        })
+        .collect::<Vec<_>>();
+    languages.sort_by_key(|language| std::cmp::Reverse(language.bytes));
+    Ok(languages)
+}
+
+/// Swaps the stored languages of a repo for the ones just fetched.
+pub(crate) async fn replace_languages(
+    transaction: &mut Transaction<'_, Postgres>,
+    repo_id: i64,
+    languages: &[LanguageUsage],
+) -> Result<(), Error> {
+    sqlx::query("DELETE FROM RepoLanguages WHERE repo_id=$1;")
+        .bind(repo_id)
+        .execute(&mut **transaction)
+        .await?;
+
+    for language in languages {
+        sqlx::query(
+            "INSERT INTO RepoLanguages( repo_id, language, bytes, share ) VALUES ( $1, $2, $3, $4 );",
+        )
+        .bind(repo_id)
+        .bind(&language.language)
+        .bind(language.bytes)
+        .bind(language.share)
+        .execute(&mut **transaction)
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Fills in the languages of each of the repos from the DB.
+pub(crate) async fn load_languages(state: &AppState, repos: &mut [Repo]) -> Result<(), Error> {
+    let repo_ids = repos.iter().map(|repo| repo.id).collect::<Vec<_>>();
+    let rows = sqlx::query_as::<_, RepoLanguage>(
+        "SELECT * FROM RepoLanguages WHERE repo_id = ANY($1) ORDER BY bytes DESC NULLS LAST, share DESC;",
+    )
+    .bind(repo_ids)
+    .fetch_all(&state.db_connection)
+    .await?;
+
+    let mut languages_by_repo: HashMap<i64, Vec<LanguageUsage>> = HashMap::new();
+    for row in rows {
+        languages_by_repo
+            .entry(row.repo_id)
+            .or_default()
+            .push(LanguageUsage {
+                language: row.language,
+                bytes: row.bytes,
+                share: row.share,
+            });
+    }
+
+    for repo in repos {
+        repo.languages = languages_by_repo.remove(&repo.id).unwrap_or_default();
+    }
+
+    Ok(())
+}
+
+/// The byte counts and the shares of every language, each summed across all
+/// of the showcased repos. Repos with shares but no byte counts don't add to
+/// the bytes, so the two are kept apart.
+pub(crate) async fn get_language_totals(state: &AppState) -> Result<Vec<LanguageUsage>, Error> {
+    let totals = sqlx::query_as::<_, LanguageUsage>(
+        "SELECT language, SUM(bytes)::BIGINT AS bytes, SUM(share) AS share FROM RepoLanguages
+        GROUP BY language ORDER BY bytes DESC NULLS LAST, share DESC;",
+    )
+    .fetch_all(&state.db_connection)
+    .await?;
+
+    Ok(totals)
+}
@@ -1,458 +1,424 @@
-use std::{cmp::Ordering, sync::Arc, time::Duration};
-
-use base64::Engine;
-use reqwest::{Client, ClientBuilder};
 use serde_derive::{Deserialize, Serialize};
 use sqlx::{
     types::chrono::{DateTime, Utc},
     FromRow,
 };
 
-use futures::future;
-
-use crate::{get_url_safe_name, AppState};
-
-const URL: &'static str = "https://api.github.com/";
-const USERNAME: &'static str = "mr-adult";
-
-pub(crate) async fn get_home(state: AppState) -> Option<BlogPost> {
-    tokio::spawn(update_data_if_necessary(state.clone()));
+use futures::{stream, StreamExt, TryStreamExt};
 
-    let result = sqlx::query_as::<_, BlogPost>(
+use crate::{
+    crates_io::{self, CrateStats},
+    error::Error,
+    get_post_url_safe_name, get_url_safe_name, AppState,
+};
+use client::GitHubClient;
+use commits::Commit;
+use languages::LanguageUsage;
+use project_links::ProjectLink;
+use releases::Release;
+use planner::{ModificationType, SyncPlan, SyncPlanReport};
+use sync_lock::SyncLock;
+use sync_runs::{SyncSummary, SyncTrigger};
+
+mod cache;
+pub(crate) mod client;
+pub(crate) mod commits;
+mod crates;
+pub(crate) mod languages;
+mod project_links;
+pub(crate) mod releases;
+mod planner;
+pub(crate) mod scheduler;
+pub(crate) mod source;
+mod sync_lock;
+pub(crate) mod sync_runs;
+pub(crate) mod webhook;
+
+/// How many repos or blog posts are downloaded at once during a sync. Each
+/// repo takes several requests, and a backfill touches every repo, so this
+/// keeps us clear of GitHub's secondary rate limit and the DB pool.
+const MAX_CONCURRENT_DOWNLOADS: usize = 4;
+
+pub(crate) async fn get_home(state: AppState) -> Result<BlogPost, Error> {
+    sqlx::query_as::<_, BlogPost>(
         "SELECT * FROM BlogPosts WHERE alphanumeric_name='Home' LIMIT 1;",
     )
-    .fetch_one(&state.db_connection)
-    .await
-    .ok()?;
-
-    Some(result)
+    .fetch_optional(&state.db_connection)
+    .await?
+    .ok_or_else(|| Error::NotFound("The home page".to_string()))
 }
 
-pub(crate) async fn get_repos(state: AppState) -> Option<Vec<Repo>> {
-    tokio::spawn(update_data_if_necessary(state.clone()));
-    get_repos_from_db(state).await
+pub(crate) async fn get_repos(state: AppState) -> Result<Vec<Repo>, Error> {
+    let mut repos = get_repos_from_db(state.clone()).await?;
+    languages::load_languages(&state, &mut repos).await?;
+    project_links::load_links(&state, &mut repos).await?;
+    crates_io::load_crates(&state, &mut repos).await?;
+    Ok(repos)
 }
 
-async fn get_repos_from_db(state: AppState) -> Option<Vec<Repo>> {
+async fn get_repos_from_db(state: AppState) -> Result<Vec<Repo>, Error> {
     let result =
         sqlx::query_as::<_, Repo>("SELECT * FROM MrAdultRepositories ORDER BY alphanumeric_name;")
             .fetch_all(&state.db_connection)
-            .await
-            .ok()?;
+            .await?;
 
-    Some(result)
+    Ok(result)
 }
 
-pub(crate) async fn get_repo(state: &AppState, name: &str) -> Option<Repo> {
-    tokio::spawn(update_data_if_necessary(state.clone()));
-
-    let result = sqlx::query_as::<_, Repo>(
+pub(crate) async fn get_repo(state: &AppState, name: &str) -> Result<Repo, Error> {
+    let mut repo = sqlx::query_as::<_, Repo>(
         "SELECT * FROM MrAdultRepositories WHERE alphanumeric_name=$1 LIMIT 1;",
     )
-    .bind(get_url_safe_name(&name))
-    .fetch_one(&state.db_connection)
-    .await
-    .ok()?;
-
-    Some(result)
+    .bind(get_url_safe_name(name))
+    .fetch_optional(&state.db_connection)
+    .await?
+    .ok_or_else(|| Error::NotFound(format!("Project {}", name)))?;
+
+    languages::load_languages(state, std::slice::from_mut(&mut repo)).await?;
+    project_links::load_links(state, std::slice::from_mut(&mut repo)).await?;
+    crates_io::load_crates(state, std::slice::from_mut(&mut repo)).await?;
+    Ok(repo)
 }
 
-pub(crate) async fn get_blog_posts(state: AppState) -> Option<Vec<BlogPost>> {
-    tokio::spawn(update_data_if_necessary(state.clone()));
-
+pub(crate) async fn get_blog_posts(state: AppState) -> Result<Vec<BlogPost>, Error> {
     let result = sqlx::query_as::<_, BlogPost>(
         "SELECT * FROM BlogPosts WHERE alphanumeric_name <> 'Home' ORDER BY alphanumeric_name;",
     )
     .fetch_all(&state.db_connection)
-    .await
-    .ok()?;
+    .await?;
 
-    Some(result)
+    Ok(result)
 }
 
-pub(crate) async fn get_blog_post(state: &AppState, name: &str) -> Option<BlogPost> {
-    tokio::spawn(update_data_if_necessary(state.clone()));
-
-    let result;
-    match sqlx::query_as::<_, BlogPost>(
-        "SELECT * FROM BlogPosts WHERE alphanumeric_name=$1 LIMIT 1;",
+pub(crate) async fn get_blog_post(state: &AppState, name: &str) -> Result<BlogPost, Error> {
+    // The dashes separate folders. Links from before posts had folders had
+    // every dash stripped, so fall back to that.
+    let url_safe_name = name
+        .chars()
+        .filter(|char| char.is_ascii_alphanumeric() || *char == '-')
+        .collect::<String>();
+    sqlx::query_as::<_, BlogPost>(
+        r#"SELECT * FROM BlogPosts WHERE alphanumeric_name IN ($1, $2)
+        ORDER BY alphanumeric_name = $1 DESC
+        LIMIT 1;"#,
     )
-    .bind(get_url_safe_name(&name))
-    .fetch_one(&state.db_connection)
-    .await {
-        Err(err) => {
-            println!("{}", err);
-            return None;
-        }
-        Ok(post) => {
-            result = post
-        }
-    }
-
-    println!("Queried successfully");
-
-    Some(result)
+    .bind(url_safe_name)
+    .bind(get_url_safe_name(name))
+    .fetch_optional(&state.db_connection)
+    .await?
+    .ok_or_else(|| Error::NotFound(format!("Blog post {}", name)))
 }
 
 pub(crate) async fn update_data_if_necessary(state: AppState) -> bool {
-    if !db_data_is_stale(&state).await {
-        return false;
-    }
+    // If another replica is already syncing, there's nothing for us to do.
+    let lock = match SyncLock::try_acquire(&state).await {
+        None => return false,
+        Some(lock) => lock,
+    };
 
-    let timeout = Duration::from_secs(5);
-    let client_result = ClientBuilder::new()
-        .danger_accept_invalid_certs(crate::ACCEPT_INVALID_CERTS)
-        .timeout(timeout)
-        .user_agent("adamfortune.com server")
-        .build();
+    // Holding the lock means no other replica can pass this check at the same time.
+    let synced = db_data_is_stale(&state).await
+        && run_sync(state.clone(), SyncTrigger::Scheduled, &lock).await;
+    lock.release().await;
+    synced
+}
 
-    let client = match client_result {
-        Ok(inner) => Arc::new(inner),
-        Err(_) => return false,
+/// Pulls the latest repos and blog posts from GitHub into the DB, regardless of
+/// how recently the last sync ran. Every run is recorded in SyncRuns.
+pub(crate) async fn sync_github_data(state: AppState, trigger: SyncTrigger) -> bool {
+    // Someone asked for this sync, so wait for any sync in progress to finish
+    // rather than skipping it. That one may have started before their change.
+    let lock = match SyncLock::acquire(&state).await {
+        None => return false,
+        Some(lock) => lock,
     };
 
-    let mut db_repos = match get_repos_from_db(state.clone()).await {
-        None => return false,
-        Some(repos) => repos,
+    let synced = run_sync(state.clone(), trigger, &lock).await;
+    lock.release().await;
+    synced
+}
+
+/// Works out what a sync would change without applying any of it. Nothing is
+/// written to the DB, and the run isn't recorded in SyncRuns.
+pub(crate) async fn plan_github_sync(state: &AppState) -> Result<SyncPlanReport, Error> {
+    let state = AppState {
+        dry_run: true,
+        ..state.clone()
     };
-    let mut github_repos = match fetch_github_repos(client.clone()).await.ok() {
-        None => return false,
-        Some(repos) => repos
+    let plan = plan_sync(&state).await?;
+    Ok(plan.report())
+}
+
+/// Runs a sync under `lock` and records it in SyncRuns, including when it's
+/// abandoned because the lock was lost.
+async fn run_sync(state: AppState, trigger: SyncTrigger, lock: &SyncLock) -> bool {
+    let run_id = sync_runs::start(&state, trigger).await;
+
+    let client = state.github_client.clone();
+    let result = match client.rate_limited_until() {
+        Some(reset_at) => Err(Error::GitHubRateLimited { reset_at }),
+        None => lock.hold_while(sync_with_source(state.clone())).await,
     };
+    record_rate_limit(&state, &client).await;
 
-    db_repos.sort_by(|repo1, repo2| repo1.id.cmp(&repo2.id));
-    github_repos.sort_by(|repo1, repo2| repo1.id.cmp(&repo2.id));
-
-    let max_iterations = github_repos.len() + db_repos.len();
-
-    let mut result = Vec::new();
-
-    let mut db_iter = db_repos.into_iter();
-    let mut current_db_value = db_iter.next();
-    let mut github_iter = github_repos.into_iter();
-    let mut current_github_value = github_iter.next();
-
-    // resolve mismatches
-    for _ in 0..max_iterations {
-        match &current_github_value {
-            None => {
-                if let Some(db_val) = current_db_value {
-                    println!("Queued repo {} for deletion", db_val.name);
-                    result.push((ModificationType::Delete, db_val));
-                }
-                for item in db_iter {
-                    // no corresponding items in github. Delete them!
-                    println!("Queued repo {} for deletion", item.name);
-                    result.push((ModificationType::Delete, item))
-                }
-                break;
-            }
-            Some(github_val) => {
-                match &current_db_value {
-                    None => {
-                        // no corresponding repos in DB. Add them!
-                        if let Some(github_val) = current_github_value {
-                            println!("Queued repo {} for upsert", github_val.name);
-                            result.push((ModificationType::Upsert, github_val));
-                        }
-                        for item in github_iter {
-                            println!("Queued repo {} for upsert", item.name);
-                            result.push((ModificationType::Upsert, item));
-                        }
-                        break;
-                    }
-                    Some(db_value) => {
-                        match github_val.id.cmp(&db_value.id) {
-                            Ordering::Less => {
-                                println!("Queued repo {} for upsert", github_val.name);
-                                result.push((
-                                    ModificationType::Upsert,
-                                    current_github_value
-                                        .expect("github value to be Some() variant"),
-                                ));
-                                // move the github cursor.
-                                current_github_value = github_iter.next();
-                            }
-                            Ordering::Equal => {
-                                if github_val.pushed_at > db_value.pushed_at
-                                    || github_val.name == "blog-posts"
-                                {
-                                    println!("Queued repo {} for upsert", github_val.name);
-                                    result.push((
-                                        ModificationType::Upsert,
-                                        current_github_value
-                                            .expect("github value to be Some() variant"),
-                                    ));
-                                } else {
-                                    println!("No changes to {}", github_val.name);
-                                    result.push((
-                                        ModificationType::None,
-                                        current_github_value
-                                            .expect("github value to be Some() variant"),
-                                    ));
-                                }
-
-                                // move both cursors.
-                                current_db_value = db_iter.next();
-                                current_github_value = github_iter.next();
-                            }
-                            Ordering::Greater => {
-                                println!("Queued repo {} for delete", db_value.name);
-                                result.push((
-                                    ModificationType::Delete,
-                                    current_db_value.expect("db value to be Some() variant"),
-                                ));
-                                // move the db cursor.
-                                current_db_value = db_iter.next();
-                            }
-                        }
-                    }
-                }
-            }
-        };
+    match &result {
+        Err(err) => println!("Sync failed. {}", err),
+        Ok(summary) => println!("Sync finished. {:?}", summary),
     }
 
-    let mut repo_modifications = Vec::new();
-    let mut repo_deletes = Vec::new();
+    if let Some(run_id) = run_id {
+        sync_runs::finish(&state, run_id, &result).await;
+    }
 
-    for repo in result
-        .into_iter()
-        .filter(|repo_result| repo_result.0 != ModificationType::None)
-    {
-        if repo.1.name == "blog-posts" {
-            let mut github_blog_posts = match get_all_md_files(&repo.1, &client).await {
-                None => return false,
-                Some(read_mes) => read_mes,
-            };
-            let mut db_read_mes = match sqlx::query_as::<_, BlogPost>("SELECT * FROM BlogPosts;")
-                .fetch_all(&state.db_connection)
-                .await
-                .ok() {
-                    None => return false,
-                    Some(read_mes) => read_mes,
-                };
+    result.is_ok()
+}
 
-            let max_iterations = github_blog_posts.len() + db_read_mes.len();
+async fn sync_with_source(state: AppState) -> Result<SyncSummary, Error> {
+    let started_at = Utc::now();
+    let plan = plan_sync(&state).await?;
+    let summary = apply_plan(&state, plan).await?;
+    cache::prune(&state, started_at).await;
 
-            github_blog_posts.sort_by(|readme1, readme2| readme1.name.cmp(&readme2.name));
-            db_read_mes.sort_by(|readme1, readme2| readme1.name.cmp(&readme2.name));
+    // The project data is already in. crates.io being down only leaves the
+    // download counts a little stale, so it doesn't fail the sync.
+    if let Err(err) = crates_io::sync_crates(&state).await {
+        println!("Failed to refresh the crates.io stats. {}", err);
+    }
 
-            for github_blog_post in github_blog_posts.iter_mut() {
-                github_blog_post.name =
-                    github_blog_post.name[0..github_blog_post.name.len() - 3].to_string();
-                // chop off the ".md"
-            }
+    Ok(summary)
+}
+
+/// Takes a snapshot of the DB and of the content source and works out what
+/// has to change.
+async fn plan_sync(state: &AppState) -> Result<SyncPlan, Error> {
+    let source = &state.content_source;
+    let db_repos = get_repos_from_db(state.clone()).await?;
+    // Any page failing to load fails the whole listing. Diffing against a partial
+    // list would queue every repo on the missing pages for deletion.
+    let source_repos = source.list_projects(state).await?;
+
+    // Without anywhere to read posts from, leave the stored ones alone.
+    let blog_posts = match source.list_posts(state).await? {
+        None => Vec::new(),
+        Some(source_blog_posts) => {
+            let db_blog_posts = sqlx::query_as::<_, BlogPost>("SELECT * FROM BlogPosts;")
+                .fetch_all(&state.db_connection)
+                .await?;
+            planner::plan_blog_posts(db_blog_posts, source_blog_posts)
+        }
+    };
+
+    Ok(SyncPlan {
+        repos: planner::plan_repos(db_repos, source_repos, &state.config.repo_filter),
+        blog_posts,
+    })
+}
 
-            let mut read_mes = Vec::with_capacity(github_blog_posts.len());
-
-            let mut db_iter = db_read_mes.into_iter();
-            let mut current_db_value = db_iter.next();
-            let mut github_iter = github_blog_posts.into_iter();
-            let mut current_github_value = github_iter.next();
-
-            // resolve mismatches
-            for _ in 0..max_iterations {
-                match &current_github_value {
-                    None => {
-                        if let Some(db_value) = current_db_value {
-                            println!("Queued blog post {} for deletion", db_value.name);
-                            read_mes.push(BlogModificationType::Delete(db_value));
-                        }
-
-                        for item in db_iter {
-                            // no corresponding items in github. Delete them!
-                            println!("Queued blog post {} for deletion", item.name);
-                            read_mes.push(BlogModificationType::Delete(item))
-                        }
-                        break;
-                    }
-                    Some(github_val) => {
-                        match &current_db_value {
-                            None => {
-                                if let Some(github_val) = current_github_value {
-                                    let path = github_val.path.clone();
-                                    read_mes.push(BlogModificationType::Upsert((
-                                        github_val,
-                                        get_file_content_owned(&repo.1, &client, path),
-                                    )));
-                                }
-
-                                for item in github_iter {
-                                    // no corresponding repo in DB. Add it!
-                                    let path = item.path.clone();
-                                    println!("Queued blog post {} for upsert", item.name);
-                                    read_mes.push(BlogModificationType::Upsert((
-                                        item,
-                                        get_file_content_owned(&repo.1, &client, path),
-                                    )));
-                                }
-                                break;
-                            }
-                            Some(db_value) => {
-                                match github_val.name.cmp(&db_value.name) {
-                                    std::cmp::Ordering::Less => {
-                                        let path = github_val.path.clone();
-                                        println!("Queued blog post {} for upsert", github_val.name);
-                                        read_mes.push(BlogModificationType::Upsert((
-                                            current_github_value
-                                                .expect("github value to be Some() variants"),
-                                            get_file_content_owned(&repo.1, &client, path),
-                                        )));
-                                        // move the github cursor.
-                                        current_github_value = github_iter.next();
-                                    }
-                                    std::cmp::Ordering::Equal => {
-                                        if github_val.sha != db_value.sha {
-                                            let path = github_val.path.clone();
-                                            println!(
-                                                "Queued blog post {} for upsert",
-                                                github_val.name
-                                            );
-                                            read_mes.push(BlogModificationType::Upsert((
-                                                current_github_value.expect(
-                                                    "current_github_value to be Some() variants",
-                                                ),
-                                                get_file_content_owned(&repo.1, &client, path),
-                                            )));
-                                        } else {
-                                            println!("No changes to {}", github_val.name);
-                                            read_mes.push(BlogModificationType::None);
-                                        }
-
-                                        // move both cursors.
-                                        current_db_value = db_iter.next();
-                                        current_github_value = github_iter.next();
-                                    }
-                                    std::cmp::Ordering::Greater => {
-                                        println!("Queued blog post {} for deletion", db_value.name);
-                                        read_mes.push(BlogModificationType::Delete(
-                                            current_db_value
-                                                .expect("current_db_value to be Some() variant"),
-                                        ));
-                                        // move the db cursor.
-                                        current_db_value = db_iter.next();
-                                    }
-                                }
-                            }
-                        };
-                    }
-                };
+/// Downloads everything the plan needs, then writes it to the DB.
+async fn apply_plan(state: &AppState, plan: SyncPlan) -> Result<SyncSummary, Error> {
+    // Download everything before touching the DB. If any of it fails, we give up
+    // without writing anything.
+    let mut repo_upserts = Vec::new();
+    let mut repo_deletes = Vec::new();
+    let mut release_refreshes = Vec::new();
+    for modification in plan.repos {
+        match modification {
+            ModificationType::Delete(repo) => repo_deletes.push(repo),
+            ModificationType::Upsert(mut repo) => {
+                let state = state.clone();
+                repo_upserts.push(async move {
+                    let source = state.content_source.clone();
+                    repo.readme = source.fetch_file(&state, Some(&repo), "README.md").await?;
+                    repo.languages = source.list_languages(&state, &repo).await?;
+                    repo.crate_names = crates::detect_crates(&state, &*source, &repo).await?;
+                    repo.releases = source.list_releases(&state, &repo).await?;
+                    // Repos that weren't pushed to are answered from the
+                    // response cache, so this only costs a request when
+                    // there are new commits.
+                    repo.commits = source.list_commits(&state, &repo).await?;
+                    Ok::<_, Error>(repo)
+                });
             }
+            ModificationType::None(repo) => {
+                println!("No changes to {}", repo.name);
+                // A release can be published for a tag that was pushed
+                // earlier, which doesn't move pushed_at. GitHub answers
+                // unchanged releases with a 304, so this is cheap there.
+                let state = state.clone();
+                release_refreshes.push(async move {
+                    let releases = state.content_source.list_releases(&state, &repo).await?;
+                    Ok::<_, Error>((repo.id, releases))
+                });
+            }
+        }
+    }
 
-            let mut blog_post_upsert_queries = Vec::new();
-
-            for read_me in read_mes.into_iter() {
-                match read_me {
-                    BlogModificationType::Upsert((metadata, future)) => {
-                        // UPSERT
-                        let md_content = future.await;
-
-                        let mut description_lines = Vec::new();
-                        let mut content_lines = Vec::new();
-                        match &md_content {
-                            None => {
-                                description_lines = Vec::with_capacity(0);
-                                content_lines = Vec::with_capacity(0);
-                            }
-                            Some(content) => {
-                                for line in content.lines() {
-                                    if line.starts_with("///") {
-                                        description_lines.push(line[3..].to_string());
-                                    } else {
-                                        content_lines.push(line);
-                                    }
-                                }
-                            }
-                        }
-
-                        blog_post_upsert_queries.push(
-                            sqlx::query(
-                                r#"INSERT INTO BlogPosts( name, alphanumeric_name, description, sha, content ) 
-                                VALUES ( $1, $2, $3, $4, $5 ) 
-                                ON CONFLICT (alphanumeric_name) DO
-                                UPDATE SET 
-                                    name = EXCLUDED.name,
-                                    alphanumeric_name = EXCLUDED.alphanumeric_name,
-                                    description = EXCLUDED.description,
-                                    sha = EXCLUDED.sha,
-                                    content = EXCLUDED.content;"#
-                            ).bind(metadata.name.clone())
-                                .bind(get_url_safe_name(&metadata.name))
-                                .bind(description_lines.join(" "))
-                                .bind(metadata.sha)
-                                .bind(content_lines.join("\n"))
-                                .execute(&state.db_connection)
-                        );
-                    }
-                    BlogModificationType::Delete(blog_post) => {
-                        println!("Deleting {}", blog_post.name);
-                        blog_post_upsert_queries.push(
-                            sqlx::query("DELETE FROM BlogPosts WHERE id=$1;")
-                                .bind(blog_post.id)
-                                .execute(&state.db_connection),
-                        );
-                    }
-                    BlogModificationType::None => {}
-                }
+    let mut blog_post_upserts = Vec::new();
+    let mut blog_post_deletes = Vec::new();
+    for modification in plan.blog_posts {
+        match modification {
+            ModificationType::Delete(blog_post) => blog_post_deletes.push(blog_post),
+            ModificationType::Upsert(metadata) => {
+                let state = state.clone();
+                blog_post_upserts.push(async move {
+                    // The file came from the listing, so it has to be there.
+                    let md_content = state
+                        .content_source
+                        .fetch_post(&state, &metadata)
+                        .await?
+                        .ok_or_else(|| Error::NotFound(metadata.path.clone()))?;
+                    Ok::<_, Error>((metadata, md_content))
+                });
             }
+            ModificationType::None(metadata) => println!("No changes to {}", metadata.path),
+        }
+    }
 
-            future::join_all(blog_post_upsert_queries).await;
-        } else {
-            let db_connection = state.db_connection.clone();
-            match repo.0 {
-                ModificationType::Delete => repo_deletes.push(async move {
-                    match sqlx::query(r#"DELETE FROM MrAdultRepositories WHERE id=$1"#)
-                        .bind(repo.1.id)
-                        .execute(&db_connection)
-                        .await
-                    {
-                        Ok(_) => {}
-                        Err(err) => println!("{}", err),
-                    }
-                }),
-                ModificationType::Upsert => {
-                    let mut repo = repo.1;
-                    let client = client.clone();
-                    let db_connection = state.db_connection.clone();
-                    repo_modifications.push(async move {
-                        repo.readme = get_read_me(&repo, &client).await;
-
-                        // UPSERT
-                        match sqlx::query(
-                            r#"INSERT INTO MrAdultRepositories( id, name, alphanumeric_name, url, html_url, description, updated_at, readme ) 
-                            VALUES ( $1, $2, $3, $4, $5, $6, $7, $8 ) 
-                            ON CONFLICT (id) DO
-                            UPDATE SET 
-                                name = EXCLUDED.name,
-                                alphanumeric_name = EXCLUDED.alphanumeric_name,
-                                url = EXCLUDED.url,
-                                html_url = EXCLUDED.html_url,
-                                description = EXCLUDED.description,
-                                updated_at = EXCLUDED.updated_at,
-                                readme = EXCLUDED.readme;"#
-                        ).bind(repo.id)
-                            .bind(repo.name.clone())
-                            .bind(get_url_safe_name(&repo.name))
-                            .bind(repo.url)
-                            .bind(repo.html_url)
-                            .bind(repo.description)
-                            .bind(repo.pushed_at)
-                            .bind(repo.readme)
-                            .execute(&db_connection)
-                            .await {
-                                Ok(_) => {}
-                                Err(err) => println!("{}", err)
-                            }
-                    });
-                }
-                ModificationType::None => {}
+    let repo_upserts = stream::iter(repo_upserts)
+        .buffer_unordered(MAX_CONCURRENT_DOWNLOADS)
+        .try_collect::<Vec<_>>()
+        .await?;
+    let release_refreshes = stream::iter(release_refreshes)
+        .buffer_unordered(MAX_CONCURRENT_DOWNLOADS)
+        .try_collect::<Vec<_>>()
+        .await?;
+    let blog_post_upserts = stream::iter(blog_post_upserts)
+        .buffer_unordered(MAX_CONCURRENT_DOWNLOADS)
+        .try_collect::<Vec<_>>()
+        .await?;
+
+    // Apply every change in one transaction so visitors never see half a sync.
+    // Returning early drops the transaction, which rolls it back.
+    let mut transaction = state.db_connection.begin().await?;
+
+    let mut summary = SyncSummary::default();
+
+    for repo in repo_deletes {
+        println!("Deleting repo {}", repo.name);
+        sqlx::query(r#"DELETE FROM MrAdultRepositories WHERE id=$1"#)
+            .bind(repo.id)
+            .execute(&mut *transaction)
+            .await?;
+        summary.repos_deleted += 1;
+    }
+
+    for repo in repo_upserts {
+        println!("Upserting repo {}", repo.name);
+        // UPSERT
+        sqlx::query(
+            r#"INSERT INTO MrAdultRepositories( id, name, alphanumeric_name, url, html_url, description, updated_at, readme,
+                topics, language, stargazers_count, forks_count, license_spdx_id, homepage, created_at, archived, fork, crate_names, needs_resync ) 
+            VALUES ( $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, FALSE ) 
+            ON CONFLICT (id) DO
+            UPDATE SET 
+                name = EXCLUDED.name,
+                alphanumeric_name = EXCLUDED.alphanumeric_name,
+                url = EXCLUDED.url,
+                html_url = EXCLUDED.html_url,
+                description = EXCLUDED.description,
+                updated_at = EXCLUDED.updated_at,
+                readme = EXCLUDED.readme,
+                topics = EXCLUDED.topics,
+                language = EXCLUDED.language,
+                stargazers_count = EXCLUDED.stargazers_count,
+                forks_count = EXCLUDED.forks_count,
+                license_spdx_id = EXCLUDED.license_spdx_id,
+                homepage = EXCLUDED.homepage,
+                created_at = EXCLUDED.created_at,
+                archived = EXCLUDED.archived,
+                fork = EXCLUDED.fork,
+                crate_names = EXCLUDED.crate_names,
+                needs_resync = EXCLUDED.needs_resync;"#
+        ).bind(repo.id)
+            .bind(repo.name.clone())
+            .bind(get_url_safe_name(&repo.name))
+            .bind(repo.url)
+            .bind(repo.html_url)
+            .bind(repo.description)
+            .bind(repo.pushed_at)
+            .bind(repo.readme)
+            .bind(repo.topics)
+            .bind(repo.language)
+            .bind(repo.stargazers_count)
+            .bind(repo.forks_count)
+            .bind(repo.license_spdx_id)
+            .bind(repo.homepage)
+            .bind(repo.created_at)
+            .bind(repo.archived)
+            .bind(repo.fork)
+            .bind(&repo.crate_names)
+            .execute(&mut *transaction)
+            .await?;
+        languages::replace_languages(&mut transaction, repo.id, &repo.languages).await?;
+        releases::replace_releases(&mut transaction, repo.id, &repo.releases).await?;
+        commits::replace_commits(&mut transaction, repo.id, &repo.commits).await?;
+        summary.repos_upserted += 1;
+    }
+
+    for (repo_id, releases) in release_refreshes {
+        releases::replace_releases(&mut transaction, repo_id, &releases).await?;
+    }
+
+    for blog_post in blog_post_deletes {
+        println!("Deleting {}", blog_post.name);
+        sqlx::query("DELETE FROM BlogPosts WHERE id=$1;")
+            .bind(blog_post.id)
+            .execute(&mut *transaction)
+            .await?;
+        summary.blog_posts_deleted += 1;
+    }
+
+    for (metadata, md_content) in blog_post_upserts {
+        let mut description_lines = Vec::new();
+        let mut content_lines = Vec::new();
+        for line in md_content.lines() {
+            if let Some(description_line) = line.strip_prefix("///") {
+                description_lines.push(description_line.to_string());
+            } else {
+                content_lines.push(line);
             }
         }
+
+        // UPSERT
+        sqlx::query(
+            r#"INSERT INTO BlogPosts( name, alphanumeric_name, description, sha, content, path, updated_at ) 
+            VALUES ( $1, $2, $3, $4, $5, $6, $7 ) 
+            ON CONFLICT (path) DO
+            UPDATE SET 
+                name = EXCLUDED.name,
+                alphanumeric_name = EXCLUDED.alphanumeric_name,
+                description = EXCLUDED.description,
+                sha = EXCLUDED.sha,
+                content = EXCLUDED.content,
+                updated_at = EXCLUDED.updated_at;"#
+        ).bind(metadata.name.clone())
+            // Include the folders in the URL so posts with the same
+            // file name in different folders don't collide.
+            .bind(get_post_url_safe_name(&metadata.path))
+            .bind(description_lines.join(" "))
+            .bind(metadata.sha)
+            .bind(content_lines.join("\n"))
+            .bind(metadata.path)
+            .bind(metadata.updated_at)
+            .execute(&mut *transaction)
+            .await?;
+        summary.blog_posts_upserted += 1;
     }
 
-    future::join_all(repo_deletes).await;
-    future::join_all(repo_modifications).await;
-    return true;
+    transaction.commit().await?;
+    Ok(summary)
+}
+
+/// Persists the rate limit state so other replicas (and restarts) don't start
+/// a sync that's bound to fail.
+async fn record_rate_limit(state: &AppState, client: &GitHubClient) {
+    if let Err(err) = sqlx::query("UPDATE GitHubQueryState SET rate_limit_reset = $1;")
+        .bind(client.rate_limited_until())
+        .execute(&state.db_connection)
+        .await
+    {
+        crate::utils::log_error(err);
+    }
 }
 
 async fn db_data_is_stale(state: &AppState) -> bool {
@@ -467,142 +433,53 @@ async fn db_data_is_stale(state: &AppState) -> bool {
         return false;
     }
 
-    let time_stamp: DateTime<Utc>;
-    match time_stamp_result {
+    let time_stamp: DateTime<Utc> = match time_stamp_result {
         // failed to connect. Just treat data as up-to-date
         None => return false,
-        Some(time_stamp_result) => {
-            time_stamp = time_stamp_result.last_queried;
+        Some(GitHubQueryState {
+            rate_limit_reset: Some(rate_limit_reset),
+            ..
+        }) if rate_limit_reset > Utc::now() => {
+            // A sync now would just burn requests we don't have.
+            return false;
         }
-    }
+        Some(time_stamp_result) => time_stamp_result.last_queried,
+    };
 
-    if time_stamp < (Utc::now() - chrono::Duration::hours(1)) {
-        sqlx::query(
-            r#"UPDATE GitHubQueryState SET last_queried = 
-                CASE WHEN (last_queried + INTERVAL '1 HOUR') > NOW() 
-                THEN last_queried 
-                ELSE NOW() 
-                END;"#,
-        )
-        .execute(&state.db_connection)
-        .await
-        .ok();
+    let interval_secs = state.config.sync_interval_secs as i64;
+    if time_stamp < (Utc::now() - chrono::Duration::seconds(interval_secs)) {
+        // The caller holds the sync lock, so nobody else can be racing us here.
+        sqlx::query("UPDATE GitHubQueryState SET last_queried = NOW();")
+            .execute(&state.db_connection)
+            .await
+            .ok();
 
-        return true;
+        true
     } else {
         // up to date - no updates needed.
-        return false;
+        false
     }
 }
 
-async fn fetch_github_repos(client: Arc<Client>) -> Result<Vec<Repo>, ()> {
-    let mut get_all_repos_url = URL.to_string();
-    get_all_repos_url.push_str(&format!("users/{}/repos", USERNAME));
-
-    let response = client
-        .get(&get_all_repos_url)
-        .query(&[("username", "mr-adult")])
-        .send()
-        .await;
-
-    let response = match response {
-        Err(_) => Err(())?,
-        Ok(inner) => inner,
-    };
-
-    let json: Result<Vec<Repo>, _> = response.json().await;
-
-    let repos = match json {
-        Err(err) => {
-            println!("{:?}", err);
-            return Err(());
-        }
-        Ok(json) => json,
-    };
-
-    Ok(repos)
-}
-
-async fn get_read_me(repo: &Repo, client: &Client) -> Option<String> {
-    get_file_content(repo, client, "README.md").await
-}
-
-async fn get_file_content_owned(repo: &Repo, client: &Client, path: String) -> Option<String> {
-    get_file_content(repo, client, &path).await
-}
-
-async fn get_file_content(repo: &Repo, client: &Client, path: &str) -> Option<String> {
-    let mut get_repo_content_url = URL.to_owned();
-    get_repo_content_url.push_str(&format!(
-        "repos/{}/{}/contents/{}",
-        USERNAME, &repo.name, path
-    ));
-
-    let response = client
-        .get(&get_repo_content_url)
-        .query(&[("owner", USERNAME), ("repo", &repo.name), ("path", path)])
-        .send()
-        .await;
-
-    let response = match response {
-        Err(_) => return None,
-        Ok(inner) => inner,
-    };
-
-    let file_content: Result<Readme, _> = response.json().await;
-
-    match file_content {
-        Err(err) => {
-            println!("{:?}", err);
+/// Pulls the `rel="next"` URL out of a GitHub `Link` header, if there is one.
+/// The header looks like `<https://...?page=2>; rel="next", <https://...?page=5>; rel="last"`.
+pub(crate) fn get_next_page_url(link: &str) -> Option<String> {
+    link.split(',').find_map(|entry| {
+        let mut parts = entry.split(';');
+        let url = parts.next()?.trim().strip_prefix('<')?.strip_suffix('>')?;
+        if parts.any(|param| param.trim() == r#"rel="next""#) {
+            Some(url.to_string())
+        } else {
             None
         }
-        Ok(file_content) => {
-            let engine = base64::engine::general_purpose::GeneralPurpose::new(
-                &base64::alphabet::STANDARD,
-                base64::engine::GeneralPurposeConfig::new(),
-            );
-            match engine.decode(file_content.content.replace("\n", "")) {
-                Err(err) => {
-                    println!("{:?}", err);
-                    None
-                }
-                Ok(str) => Some(String::from_utf8_lossy(str.as_slice()).to_string()),
-            }
-        }
-    }
+    })
 }
 
-async fn get_all_md_files(repo: &Repo, client: &Client) -> Option<Vec<FileMetadata>> {
-    let mut get_repo_content_url = URL.to_owned();
-    get_repo_content_url.push_str(&format!("repos/{}/{}/contents/", USERNAME, &repo.name));
-
-    let response = client
-        .get(&get_repo_content_url)
-        .query(&[("owner", USERNAME), ("repo", &repo.name)])
-        .send()
-        .await;
-
-    let response = match response {
-        Err(_) => return None,
-        Ok(inner) => inner,
-    };
-
-    let files: Result<Vec<FileMetadata>, _> = response.json().await;
-
-    match files {
-        Err(err) => {
-            crate::utils::log_error(err);
-            None
-        }
-        Ok(files) => {
-            return Some(
-                files
-                    .into_iter()
-                    .filter(|file| file.path.ends_with(".md"))
-                    .collect(),
-            );
-        }
-    }
+pub(crate) fn decode<T: serde::de::DeserializeOwned>(url: &str, body: &str) -> Result<T, Error> {
+    serde_json::from_str(body).map_err(|err| Error::Decode {
+        url: url.to_string(),
+        message: err.to_string(),
+    })
 }
 
 #[derive(Clone, Debug, Default, FromRow)]
@@ -610,6 +487,7 @@ pub struct GitHubQueryState {
     #[allow(unused)]
     id: i32,
     last_queried: DateTime<Utc>,
+    rate_limit_reset: Option<DateTime<Utc>>,
 }
 
 #[derive(Clone, Debug, Default, Deserialize, Serialize, FromRow)]
@@ -622,6 +500,68 @@ pub(crate) struct Repo {
     #[sqlx(rename = "updated_at")]
     pub(crate) pushed_at: DateTime<Utc>,
     pub(crate) readme: Option<String>,
+    #[serde(default)]
+    pub(crate) topics: Vec<String>,
+    /// The language GitHub detected the most of.
+    pub(crate) language: Option<String>,
+    pub(crate) stargazers_count: i32,
+    pub(crate) forks_count: i32,
+    /// GitHub nests this in a license object, which we flatten to its id.
+    #[serde(rename = "license", deserialize_with = "deserialize_license_spdx_id", default)]
+    pub(crate) license_spdx_id: Option<String>,
+    pub(crate) homepage: Option<String>,
+    /// Only `None` for rows that haven't been synced since this was added.
+    pub(crate) created_at: Option<DateTime<Utc>>,
+    pub(crate) archived: bool,
+    pub(crate) fork: bool,
+    /// Set by migrations that need every repo fetched again. The next sync
+    /// upserts the repo whether or not it changed, which clears it.
+    #[serde(skip)]
+    pub(crate) needs_resync: bool,
+    /// The crates.io crates published from the repo, found in its Cargo.toml
+    /// files while syncing.
+    #[serde(skip)]
+    pub(crate) crate_names: Vec<String>,
+    /// Stored in Crates, and only loaded for the pages that show it.
+    #[serde(skip)]
+    #[sqlx(skip)]
+    pub(crate) crates: Vec<CrateStats>,
+    /// Stored in RepoLanguages, and only loaded for the pages that show it.
+    #[serde(skip)]
+    #[sqlx(skip)]
+    pub(crate) languages: Vec<LanguageUsage>,
+    /// Stored in ProjectLinks, and only loaded for the pages that show it.
+    #[serde(skip)]
+    #[sqlx(skip)]
+    pub(crate) links: Vec<ProjectLink>,
+    /// Stored in Releases. Only filled in while syncing.
+    #[serde(skip)]
+    #[sqlx(skip)]
+    pub(crate) releases: Vec<Release>,
+    /// Stored in Commits. Only filled in while syncing.
+    #[serde(skip)]
+    #[sqlx(skip)]
+    pub(crate) commits: Vec<Commit>,
+    /// Only known for repos fresh from GitHub. It isn't stored.
+    #[serde(default)]
+    #[sqlx(default)]
+    pub(crate) default_branch: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct License {
+    spdx_id: Option<String>,
+}
+
+fn deserialize_license_spdx_id<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let license = <Option<License> as serde::Deserialize>::deserialize(deserializer)?;
+    // GitHub uses NOASSERTION for licenses it couldn't identify.
+    Ok(license
+        .and_then(|license| license.spdx_id)
+        .filter(|spdx_id| spdx_id != "NOASSERTION"))
 }
 
 #[derive(Clone, Default, Deserialize, Serialize, FromRow)]
@@ -632,13 +572,24 @@ pub(crate) struct BlogPost {
     pub(crate) sha: String,
     pub(crate) description: String,
     pub(crate) content: String,
+    /// Where the post lives in the content repo, relative to its root.
+    pub(crate) path: String,
+    /// When the post was last changed, if the source knows.
+    pub(crate) updated_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Deserialize, Serialize)]
 pub(crate) struct FileMetadata {
-    sha: String,
-    name: String,
-    path: String,
+    /// Anything that changes when the content does. GitHub and GitLab use
+    /// the git blob sha.
+    pub(crate) sha: String,
+    /// The file name without its ".md" extension.
+    pub(crate) name: String,
+    pub(crate) path: String,
+    /// When the file was last changed. Only the local and git sources know
+    /// this without a request per file.
+    #[serde(default)]
+    pub(crate) updated_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Deserialize, Serialize)]
@@ -646,18 +597,28 @@ pub(crate) struct Readme {
     pub(crate) content: String,
 }
 
-#[derive(PartialEq, Eq)]
-pub(crate) enum ModificationType {
-    Delete,
-    Upsert,
-    None,
-}
 
-pub(crate) enum BlogModificationType<T>
-where
-    T: std::future::Future<Output = Option<String>>,
-{
-    Delete(BlogPost),
-    Upsert((FileMetadata, T)),
-    None,
+#[cfg(test)]
+mod tests {
+    use super::get_next_page_url;
+
+    #[test]
+    fn next_page_url_is_read_from_the_link_header() {
+        let link = r#"<https://api.github.com/user/1/repos?page=2>; rel="next", <https://api.github.com/user/1/repos?page=5>; rel="last""#;
+        assert_eq!(
+            get_next_page_url(link),
+            Some("https://api.github.com/user/1/repos?page=2".to_string())
+        );
+    }
+
+    #[test]
+    fn last_page_has_no_next_url() {
+        let link = r#"<https://api.github.com/user/1/repos?page=1>; rel="first", <https://api.github.com/user/1/repos?page=4>; rel="prev""#;
+        assert_eq!(get_next_page_url(link), None);
+    }
+
+    #[test]
+    fn empty_link_header_has_no_next_url() {
+        assert_eq!(get_next_page_url(""), None);
+    }
 }
@@ -0,0 +1,449 @@
+use std::cmp::Ordering;
+
+use serde_derive::Serialize;
+
+use super::{BlogPost, FileMetadata, Repo};
+use crate::{config::RepoFilter, utils::glob_matches};
+
+/// What a sync should do with one item, given what's in the DB (`D`) and what's
+/// on GitHub (`G`).
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum ModificationType<D, G> {
+    /// It's only in the DB. Remove it.
+    Delete(D),
+    /// It's new or changed on GitHub. Write it.
+    Upsert(G),
+    /// Both sides already agree.
+    None(G),
+}
+
+/// Everything a sync is going to change, worked out before anything is
+/// downloaded or written.
+pub(crate) struct SyncPlan {
+    pub(crate) repos: Vec<ModificationType<Repo, Repo>>,
+    pub(crate) blog_posts: Vec<ModificationType<BlogPost, FileMetadata>>,
+}
+
+impl SyncPlan {
+    /// The upserts and deletes in the plan, in a shape that can be shown to
+    /// someone. Items that won't change are left out.
+    pub(crate) fn report(&self) -> SyncPlanReport {
+        SyncPlanReport {
+            repos: self
+                .repos
+                .iter()
+                .filter_map(|modification| match modification {
+                    ModificationType::Delete(repo) => Some((PlannedAction::Delete, repo)),
+                    ModificationType::Upsert(repo) => Some((PlannedAction::Upsert, repo)),
+                    ModificationType::None(_) => None,
+                })
+                .map(|(action, repo)| PlannedRepoChange {
+                    action,
+                    id: repo.id,
+                    name: repo.name.clone(),
+                })
+                .collect(),
+            blog_posts: self
+                .blog_posts
+                .iter()
+                .filter_map(|modification| match modification {
+                    ModificationType::Delete(blog_post) => Some(PlannedBlogPostChange {
+                        action: PlannedAction::Delete,
+                        path: blog_post.path.clone(),
+                    }),
+                    ModificationType::Upsert(metadata) => Some(PlannedBlogPostChange {
+                        action: PlannedAction::Upsert,
+                        path: metadata.path.clone(),
+                    }),
+                    ModificationType::None(_) => None,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// What a dry run returns.
+#[derive(Serialize)]
+pub(crate) struct SyncPlanReport {
+    repos: Vec<PlannedRepoChange>,
+    blog_posts: Vec<PlannedBlogPostChange>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+enum PlannedAction {
+    Upsert,
+    Delete,
+}
+
+#[derive(Serialize)]
+struct PlannedRepoChange {
+    action: PlannedAction,
+    id: i64,
+    name: String,
+}
+
+#[derive(Serialize)]
+struct PlannedBlogPostChange {
+    action: PlannedAction,
+    path: String,
+}
+
+/// Lines up the DB and GitHub snapshots by key and decides what to do with each
+/// item. Items only in the DB are deleted, items only on GitHub are upserted,
+/// and items on both sides are upserted when `has_changed` says so.
+pub(crate) fn plan<D, G, K>(
+    mut db_items: Vec<D>,
+    mut github_items: Vec<G>,
+    db_key: impl Fn(&D) -> K,
+    github_key: impl Fn(&G) -> K,
+    has_changed: impl Fn(&G, &D) -> bool,
+) -> Vec<ModificationType<D, G>>
+where
+    K: Ord,
+{
+    db_items.sort_by_key(|item| db_key(item));
+    github_items.sort_by_key(|item| github_key(item));
+
+    let mut result = Vec::with_capacity(db_items.len().max(github_items.len()));
+    let mut db_iter = db_items.into_iter().peekable();
+    let mut github_iter = github_items.into_iter().peekable();
+
+    loop {
+        let ordering = match (github_iter.peek(), db_iter.peek()) {
+            (None, None) => break,
+            // no corresponding item in the DB. Add it!
+            (Some(_), None) => Ordering::Less,
+            // no corresponding item on GitHub. Delete it!
+            (None, Some(_)) => Ordering::Greater,
+            (Some(github_value), Some(db_value)) => {
+                github_key(github_value).cmp(&db_key(db_value))
+            }
+        };
+
+        match ordering {
+            Ordering::Less => {
+                let github_value = github_iter.next().expect("github value to be Some() variant");
+                result.push(ModificationType::Upsert(github_value));
+            }
+            Ordering::Greater => {
+                let db_value = db_iter.next().expect("db value to be Some() variant");
+                result.push(ModificationType::Delete(db_value));
+            }
+            Ordering::Equal => {
+                let github_value = github_iter.next().expect("github value to be Some() variant");
+                let db_value = db_iter.next().expect("db value to be Some() variant");
+                if has_changed(&github_value, &db_value) {
+                    result.push(ModificationType::Upsert(github_value));
+                } else {
+                    result.push(ModificationType::None(github_value));
+                }
+            }
+        }
+    }
+
+    result
+}
+
+/// Plans the repo changes. Repos are matched by id, so a renamed repo is
+/// updated in place rather than deleted and re-added. Repos the filter rejects
+/// are treated as if GitHub didn't have them, so they're deleted if we stored
+/// them before.
+pub(crate) fn plan_repos(
+    db_repos: Vec<Repo>,
+    github_repos: Vec<Repo>,
+    filter: &RepoFilter,
+) -> Vec<ModificationType<Repo, Repo>> {
+    let github_repos = github_repos
+        .into_iter()
+        .filter(|repo| is_showcased(filter, repo))
+        .collect();
+
+    plan(
+        db_repos,
+        github_repos,
+        |repo| repo.id,
+        |repo| repo.id,
+        |github_repo, db_repo| {
+            // Renames, stars and the like don't count as a push, so compare
+            // the fields we show as well.
+            db_repo.needs_resync
+                || github_repo.pushed_at > db_repo.pushed_at
+                || github_repo.name != db_repo.name
+                || github_repo.html_url != db_repo.html_url
+                || github_repo.description != db_repo.description
+                || github_repo.topics != db_repo.topics
+                || github_repo.language != db_repo.language
+                || github_repo.stargazers_count != db_repo.stargazers_count
+                || github_repo.forks_count != db_repo.forks_count
+                || github_repo.license_spdx_id != db_repo.license_spdx_id
+                || github_repo.homepage != db_repo.homepage
+                || github_repo.created_at != db_repo.created_at
+                || github_repo.archived != db_repo.archived
+                || github_repo.fork != db_repo.fork
+        },
+    )
+}
+
+/// Whether the repo passes every one of the filter's rules.
+pub(crate) fn is_showcased(filter: &RepoFilter, repo: &Repo) -> bool {
+    let has_topic = |topics: &[String]| {
+        topics.iter().any(|topic| {
+            repo.topics
+                .iter()
+                .any(|repo_topic| repo_topic.eq_ignore_ascii_case(topic))
+        })
+    };
+
+    (filter.include_names.is_empty()
+        || filter
+            .include_names
+            .iter()
+            .any(|pattern| glob_matches(pattern, &repo.name)))
+        && !filter
+            .exclude_names
+            .iter()
+            .any(|pattern| glob_matches(pattern, &repo.name))
+        && (filter.include_topics.is_empty() || has_topic(&filter.include_topics))
+        && !has_topic(&filter.exclude_topics)
+        && (filter.include_forks || !repo.fork)
+        && (filter.include_archived || !repo.archived)
+        && filter
+            .active_since
+            .is_none_or(|active_since| repo.pushed_at >= active_since)
+}
+
+/// Plans the blog post changes. Posts can live in folders, so the path is the
+/// only unique key. The sha tells us whether the content changed.
+pub(crate) fn plan_blog_posts(
+    db_blog_posts: Vec<BlogPost>,
+    github_files: Vec<FileMetadata>,
+) -> Vec<ModificationType<BlogPost, FileMetadata>> {
+    plan(
+        db_blog_posts,
+        github_files,
+        |blog_post| blog_post.path.clone(),
+        |file| file.path.clone(),
+        |file, blog_post| file.sha != blog_post.sha,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::types::chrono::{TimeZone, Utc};
+
+    use super::*;
+
+    /// A filter that lets every repo through.
+    fn show_everything() -> RepoFilter {
+        RepoFilter {
+            include_forks: true,
+            include_archived: true,
+            ..Default::default()
+        }
+    }
+
+    fn repo(id: i64, name: &str) -> Repo {
+        Repo {
+            id,
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn blog_post(path: &str, sha: &str) -> BlogPost {
+        BlogPost {
+            path: path.to_string(),
+            sha: sha.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn file(path: &str, sha: &str) -> FileMetadata {
+        FileMetadata {
+            sha: sha.to_string(),
+            name: path.trim_end_matches(".md").to_string(),
+            path: path.to_string(),
+            updated_at: None,
+        }
+    }
+
+    /// The plan as (action, key) pairs, which is easier to compare.
+    fn summarize_repos(plan: &[ModificationType<Repo, Repo>]) -> Vec<(&'static str, i64)> {
+        plan.iter()
+            .map(|modification| match modification {
+                ModificationType::Delete(repo) => ("delete", repo.id),
+                ModificationType::Upsert(repo) => ("upsert", repo.id),
+                ModificationType::None(repo) => ("none", repo.id),
+            })
+            .collect()
+    }
+
+    fn summarize_blog_posts(
+        plan: &[ModificationType<BlogPost, FileMetadata>],
+    ) -> Vec<(&'static str, &str)> {
+        plan.iter()
+            .map(|modification| match modification {
+                ModificationType::Delete(blog_post) => ("delete", blog_post.path.as_str()),
+                ModificationType::Upsert(file) => ("upsert", file.path.as_str()),
+                ModificationType::None(file) => ("none", file.path.as_str()),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn plan_of_two_empty_sides_is_empty() {
+        let plan = plan(
+            Vec::<i32>::new(),
+            Vec::<i32>::new(),
+            |d| *d,
+            |g| *g,
+            |_, _| true,
+        );
+        assert!(plan.is_empty());
+    }
+
+    #[test]
+    fn plan_matches_items_by_key() {
+        let plan = plan(vec![1, 2, 4], vec![4, 3, 1], |d| *d, |g| *g, |g, _| *g == 4);
+        assert_eq!(
+            plan,
+            vec![
+                ModificationType::None(1),
+                ModificationType::Delete(2),
+                ModificationType::Upsert(3),
+                ModificationType::Upsert(4),
+            ]
+        );
+    }
+
+    #[test]
+    fn every_repo_is_upserted_into_an_empty_db() {
+        let plan = plan_repos(
+            Vec::new(),
+            vec![repo(2, "b"), repo(1, "a")],
+            &show_everything(),
+        );
+        assert_eq!(summarize_repos(&plan), vec![("upsert", 1), ("upsert", 2)]);
+    }
+
+    #[test]
+    fn every_repo_is_deleted_when_the_source_has_none() {
+        let plan = plan_repos(
+            vec![repo(1, "a"), repo(2, "b")],
+            Vec::new(),
+            &show_everything(),
+        );
+        assert_eq!(summarize_repos(&plan), vec![("delete", 1), ("delete", 2)]);
+    }
+
+    #[test]
+    fn no_repos_on_either_side_plans_nothing() {
+        let plan = plan_repos(Vec::new(), Vec::new(), &show_everything());
+        assert!(plan.is_empty());
+    }
+
+    #[test]
+    fn renamed_repo_is_upserted_in_place() {
+        let plan = plan_repos(
+            vec![repo(1, "old-name")],
+            vec![repo(1, "new-name")],
+            &show_everything(),
+        );
+        assert_eq!(summarize_repos(&plan), vec![("upsert", 1)]);
+        match &plan[0] {
+            ModificationType::Upsert(repo) => assert_eq!(repo.name, "new-name"),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn unchanged_repo_is_left_alone() {
+        let plan = plan_repos(vec![repo(1, "a")], vec![repo(1, "a")], &show_everything());
+        assert_eq!(summarize_repos(&plan), vec![("none", 1)]);
+    }
+
+    #[test]
+    fn repo_flagged_for_a_resync_is_upserted() {
+        let mut db_repo = repo(1, "a");
+        db_repo.needs_resync = true;
+        let plan = plan_repos(vec![db_repo], vec![repo(1, "a")], &show_everything());
+        assert_eq!(summarize_repos(&plan), vec![("upsert", 1)]);
+    }
+
+    #[test]
+    fn repo_only_in_the_db_is_deleted() {
+        let plan = plan_repos(
+            vec![repo(1, "a"), repo(2, "b")],
+            vec![repo(1, "a")],
+            &show_everything(),
+        );
+        assert_eq!(summarize_repos(&plan), vec![("none", 1), ("delete", 2)]);
+    }
+
+    #[test]
+    fn blog_post_with_a_new_sha_is_upserted() {
+        let plan = plan_blog_posts(
+            vec![blog_post("a.md", "1"), blog_post("b.md", "2")],
+            vec![file("a.md", "1"), file("b.md", "3")],
+        );
+        assert_eq!(
+            summarize_blog_posts(&plan),
+            vec![("none", "a.md"), ("upsert", "b.md")]
+        );
+    }
+
+    /// Posts are keyed by path now, not by name, so moving one into a folder
+    /// gives it a new row even though the content didn't change.
+    #[test]
+    fn moved_blog_post_is_deleted_and_added_again() {
+        let plan = plan_blog_posts(
+            vec![blog_post("post.md", "1")],
+            vec![file("drafts/post.md", "1")],
+        );
+        assert_eq!(
+            summarize_blog_posts(&plan),
+            vec![("upsert", "drafts/post.md"), ("delete", "post.md")]
+        );
+    }
+
+    #[test]
+    fn blog_posts_against_empty_sides() {
+        let plan = plan_blog_posts(Vec::new(), vec![file("a.md", "1")]);
+        assert_eq!(summarize_blog_posts(&plan), vec![("upsert", "a.md")]);
+
+        let plan = plan_blog_posts(vec![blog_post("a.md", "1")], Vec::new());
+        assert_eq!(summarize_blog_posts(&plan), vec![("delete", "a.md")]);
+
+        assert!(plan_blog_posts(Vec::new(), Vec::new()).is_empty());
+    }
+
+    #[test]
+    fn excluded_topics_keep_a_repo_off() {
+        let filter = RepoFilter {
+            exclude_topics: vec!["Archive".to_string()],
+            ..show_everything()
+        };
+        let mut repo = repo(1, "a");
+        assert!(is_showcased(&filter, &repo));
+
+        repo.topics = vec!["rust".to_string(), "archive".to_string()];
+        assert!(!is_showcased(&filter, &repo));
+    }
+
+    #[test]
+    fn repos_pushed_to_at_active_since_are_showcased() {
+        let active_since = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let filter = RepoFilter {
+            active_since: Some(active_since),
+            ..show_everything()
+        };
+        let mut repo = repo(1, "a");
+
+        repo.pushed_at = active_since;
+        assert!(is_showcased(&filter, &repo));
+
+        repo.pushed_at = active_since - chrono::Duration::seconds(1);
+        assert!(!is_showcased(&filter, &repo));
+    }
+}
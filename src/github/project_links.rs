@@ -0,0 +1,45 @@
+use std::collections::HashMap;
+
+use sqlx::FromRow;
+
+use super::Repo;
+use crate::{error::Error, AppState};
+
+/// An extra link shown on a project's page, like its crates.io listing. These
+/// are kept in ProjectLinks by repo name, so adding one doesn't need a deploy.
+#[derive(Clone, Debug, Default, FromRow)]
+pub(crate) struct ProjectLink {
+    pub(crate) repo_name: String,
+    pub(crate) display_text: String,
+    pub(crate) href: String,
+}
+
+/// Fills in the links of each of the repos from the DB.
+pub(crate) async fn load_links(state: &AppState, repos: &mut [Repo]) -> Result<(), Error> {
+    let repo_names = repos
+        .iter()
+        .map(|repo| repo.name.to_lowercase())
+        .collect::<Vec<_>>();
+    let links = sqlx::query_as::<_, ProjectLink>(
+        "SELECT * FROM ProjectLinks WHERE LOWER(repo_name) = ANY($1) ORDER BY position, id;",
+    )
+    .bind(repo_names)
+    .fetch_all(&state.db_connection)
+    .await?;
+
+    let mut links_by_repo: HashMap<String, Vec<ProjectLink>> = HashMap::new();
+    for link in links {
+        links_by_repo
+            .entry(link.repo_name.to_lowercase())
+            .or_default()
+            .push(link);
+    }
+
+    for repo in repos {
+        repo.links = links_by_repo
+            .remove(&repo.name.to_lowercase())
+            .unwrap_or_default();
+    }
+
+    Ok(())
+}
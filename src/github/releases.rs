@@ -0,0 +1,94 @@
+use serde_derive::{Deserialize, Serialize};
+use sqlx::{
+    types::chrono::{DateTime, Utc},
+    FromRow, Postgres, Transaction,
+};
+
+use super::{cache, client::GitHubClient, decode, Repo};
+use crate::{error::Error, AppState};
+
+/// How many of a repo's most recent releases we keep.
+const RELEASES_PER_REPO: u8 = 10;
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize, FromRow)]
+pub(crate) struct Release {
+    pub(crate) id: i64,
+    pub(crate) tag_name: String,
+    pub(crate) name: Option<String>,
+    /// The release notes, in markdown.
+    pub(crate) body: Option<String>,
+    pub(crate) html_url: String,
+    pub(crate) published_at: Option<DateTime<Utc>>,
+    pub(crate) prerelease: bool,
+    /// Drafts are never stored, so this is only read from GitHub.
+    #[serde(default)]
+    #[sqlx(default)]
+    pub(crate) draft: bool,
+}
+
+/// Gets the repo's most recent published releases, newest first.
+pub(crate) async fn get_github_releases(
+    state: &AppState,
+    client: &GitHubClient,
+    repo: &Repo,
+) -> Result<Vec<Release>, Error> {
+    let mut get_releases_url = state.config.github_api_url.clone();
+    get_releases_url.push_str(&format!(
+        "repos/{}/{}/releases?per_page={}",
+        state.config.github_owner, &repo.name, RELEASES_PER_REPO
+    ));
+
+    let response = match cache::conditional_get(state, client, &get_releases_url).await? {
+        None => return Ok(Vec::new()),
+        Some(response) => response,
+    };
+
+    let releases = decode::<Vec<Release>>(&get_releases_url, &response.body)?;
+    Ok(releases
+        .into_iter()
+        .filter(|release| !release.draft)
+        .collect())
+}
+
+/// Swaps the stored releases of a repo for the ones just fetched.
+pub(crate) async fn replace_releases(
+    transaction: &mut Transaction<'_, Postgres>,
+    repo_id: i64,
+    releases: &[Release],
+) -> Result<(), Error> {
+    sqlx::query("DELETE FROM Releases WHERE repo_id=$1;")
+        .bind(repo_id)
+        .execute(&mut **transaction)
+        .await?;
+
+    for release in releases {
+        sqlx::query(
+            r#"INSERT INTO Releases( id, repo_id, tag_name, name, body, html_url, published_at, prerelease )
+            VALUES ( $1, $2, $3, $4, $5, $6, $7, $8 );"#,
+        )
+        .bind(release.id)
+        .bind(repo_id)
+        .bind(&release.tag_name)
+        .bind(&release.name)
+        .bind(&release.body)
+        .bind(&release.html_url)
+        .bind(release.published_at)
+        .bind(release.prerelease)
+        .execute(&mut **transaction)
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// The stored releases of a repo, newest first.
+pub(crate) async fn get_releases(state: &AppState, repo_id: i64) -> Result<Vec<Release>, Error> {
+    let releases = sqlx::query_as::<_, Release>(
+        "SELECT * FROM Releases WHERE repo_id=$1 ORDER BY published_at DESC NULLS LAST;",
+    )
+    .bind(repo_id)
+    .fetch_all(&state.db_connection)
+    .await?;
+
+    Ok(releases)
+}
@@ -0,0 +1,56 @@
+use std::time::Duration;
+
+use rand::Rng;
+use tokio::sync::mpsc::{self, error::TrySendError};
+
+use super::sync_runs::SyncTrigger;
+use crate::AppState;
+
+/// Starts the one background task that keeps the DB in sync with GitHub. It
+/// checks for stale data on a timer, and runs a sync straight away when one is
+/// requested through `request_sync`.
+pub(crate) fn start(state: AppState, mut sync_requests: mpsc::Receiver<SyncTrigger>) {
+    tokio::spawn(async move {
+        // Check shortly after startup so a fresh deploy doesn't wait a whole interval.
+        let mut delay = get_jitter(&state);
+        loop {
+            let trigger = tokio::select! {
+                _ = tokio::time::sleep(delay) => SyncTrigger::Scheduled,
+                request = sync_requests.recv() => match request {
+                    // Every sender is gone, so the server is shutting down.
+                    None => return,
+                    Some(trigger) => trigger,
+                },
+            };
+
+            // Each sync runs in its own task, so a panic mid-sync is contained
+            // there and the scheduler keeps going.
+            let sync = match trigger {
+                SyncTrigger::Scheduled => {
+                    tokio::spawn(super::update_data_if_necessary(state.clone()))
+                }
+                trigger => tokio::spawn(super::sync_github_data(state.clone(), trigger)),
+            };
+            if let Err(err) = sync.await {
+                println!("The sync task panicked. Error: \n{}", err);
+            }
+
+            delay = Duration::from_secs(state.config.sync_interval_secs) + get_jitter(&state);
+        }
+    });
+}
+
+/// Asks the scheduler to sync as soon as it can. Requests that arrive while one
+/// is already queued are folded into it.
+pub(crate) fn request_sync(state: &AppState, trigger: SyncTrigger) {
+    match state.sync_requests.try_send(trigger) {
+        Ok(()) | Err(TrySendError::Full(_)) => {}
+        Err(TrySendError::Closed(_)) => println!("The sync scheduler isn't running"),
+    }
+}
+
+/// A random extra delay so replicas started together don't all wake up at once.
+fn get_jitter(state: &AppState) -> Duration {
+    let jitter_secs = state.config.sync_jitter_secs;
+    Duration::from_secs(rand::thread_rng().gen_range(0..=jitter_secs))
+}
@@ -0,0 +1,204 @@
+use async_trait::async_trait;
+use base64::Engine;
+use serde_derive::Deserialize;
+
+use super::{
+    cache, commits, commits::Commit, decode, get_next_page_url, languages,
+    languages::LanguageUsage, releases, releases::Release, FileMetadata, Readme, Repo,
+};
+use crate::{
+    content_source::{get_post_name, ContentSource},
+    error::Error,
+    AppState,
+};
+
+/// Reads the projects from the owner's GitHub repos, and the blog posts from
+/// the content repo. All of it goes through the shared `GitHubClient`, so the
+/// rate limit and response cache cover every request.
+pub(crate) struct GitHubSource;
+
+/// One page of a repo listing. The installation endpoint wraps its page in an
+/// object, the others return a bare array.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RepoPage {
+    Repos(Vec<Repo>),
+    Installation { repositories: Vec<Repo> },
+}
+
+#[derive(Deserialize)]
+struct GitTree {
+    tree: Vec<GitTreeEntry>,
+    truncated: bool,
+}
+
+#[derive(Deserialize)]
+struct GitTreeEntry {
+    path: String,
+    r#type: String,
+    sha: String,
+}
+
+#[derive(Deserialize)]
+struct DirectoryEntry {
+    name: String,
+    r#type: String,
+}
+
+#[async_trait]
+impl ContentSource for GitHubSource {
+    /// Fetches every repo on the account, following the `Link` headers through
+    /// all of the pages. If any page fails to load, the whole listing fails.
+    async fn list_projects(&self, state: &AppState) -> Result<Vec<Repo>, Error> {
+        let client = &state.github_client;
+        let per_page = state.config.github_per_page;
+        let owner = &state.config.github_owner;
+        let mut get_all_repos_url = state.config.github_api_url.clone();
+        // The users/ endpoint only ever lists public repos. Private ones have to come
+        // from the endpoints scoped to whoever we're authenticated as.
+        get_all_repos_url.push_str(&if !state.config.github_include_private {
+            format!("users/{}/repos?per_page={}", owner, per_page)
+        } else if client.is_app_installation() {
+            format!("installation/repositories?per_page={}", per_page)
+        } else {
+            format!("user/repos?affiliation=owner&visibility=all&per_page={}", per_page)
+        });
+
+        let mut repos = Vec::new();
+        let mut next_page_url = Some(get_all_repos_url);
+        while let Some(page_url) = next_page_url {
+            let response = match cache::conditional_get(state, client, &page_url).await? {
+                None => return Err(Error::NotFound(page_url)),
+                Some(response) => response,
+            };
+
+            match decode::<RepoPage>(&page_url, &response.body)? {
+                RepoPage::Repos(page) => repos.extend(page),
+                RepoPage::Installation { repositories } => repos.extend(repositories),
+            }
+            next_page_url = response.link.as_deref().and_then(get_next_page_url);
+        }
+
+        // The content repo holds the blog posts. It isn't a project.
+        repos.retain(|repo| repo.name != state.config.github_content_repo);
+        Ok(repos)
+    }
+
+    /// Walks the whole tree of the content repo's default branch and returns
+    /// every markdown file in it, including the ones in folders.
+    async fn list_posts(&self, state: &AppState) -> Result<Option<Vec<FileMetadata>>, Error> {
+        let mut get_repo_tree_url = state.config.github_api_url.clone();
+        get_repo_tree_url.push_str(&format!(
+            "repos/{}/{}/git/trees/HEAD?recursive=1",
+            state.config.github_owner, state.config.github_content_repo
+        ));
+
+        let response =
+            match cache::conditional_get(state, &state.github_client, &get_repo_tree_url).await? {
+                None => return Ok(None),
+                Some(response) => response,
+            };
+
+        let tree = decode::<GitTree>(&get_repo_tree_url, &response.body)?;
+        if tree.truncated {
+            // A partial listing would delete every post that didn't make the cut.
+            return Err(Error::Decode {
+                url: get_repo_tree_url,
+                message: "The tree is too large to list in one request".to_string(),
+            });
+        }
+
+        Ok(Some(
+            tree.tree
+                .into_iter()
+                .filter(|entry| entry.r#type == "blob" && entry.path.ends_with(".md"))
+                .map(|entry| FileMetadata {
+                    name: get_post_name(&entry.path),
+                    sha: entry.sha,
+                    path: entry.path,
+                    updated_at: None,
+                })
+                .collect(),
+        ))
+    }
+
+    async fn fetch_file(
+        &self,
+        state: &AppState,
+        project: Option<&Repo>,
+        path: &str,
+    ) -> Result<Option<String>, Error> {
+        let repo_name = match project {
+            None => &state.config.github_content_repo,
+            Some(project) => &project.name,
+        };
+        let mut get_repo_content_url = state.config.github_api_url.clone();
+        get_repo_content_url.push_str(&format!(
+            "repos/{}/{}/contents/{}",
+            state.config.github_owner, repo_name, path
+        ));
+
+        let response =
+            match cache::conditional_get(state, &state.github_client, &get_repo_content_url)
+                .await?
+            {
+                None => return Ok(None),
+                Some(response) => response,
+            };
+
+        let file_content = decode::<Readme>(&get_repo_content_url, &response.body)?;
+
+        let engine = base64::engine::general_purpose::GeneralPurpose::new(
+            &base64::alphabet::STANDARD,
+            base64::engine::GeneralPurposeConfig::new(),
+        );
+        match engine.decode(file_content.content.replace('\n', "")) {
+            Err(err) => Err(Error::Decode {
+                url: get_repo_content_url,
+                message: err.to_string(),
+            }),
+            Ok(str) => Ok(Some(String::from_utf8_lossy(str.as_slice()).to_string())),
+        }
+    }
+
+    async fn list_dirs(
+        &self,
+        state: &AppState,
+        project: &Repo,
+        path: &str,
+    ) -> Result<Vec<String>, Error> {
+        let mut get_dir_url = state.config.github_api_url.clone();
+        get_dir_url.push_str(&format!(
+            "repos/{}/{}/contents/{}",
+            state.config.github_owner, &project.name, path
+        ));
+
+        let response = match cache::conditional_get(state, &state.github_client, &get_dir_url).await? {
+            None => return Ok(Vec::new()),
+            Some(response) => response,
+        };
+
+        let entries = decode::<Vec<DirectoryEntry>>(&get_dir_url, &response.body)?;
+        Ok(entries
+            .into_iter()
+            .filter(|entry| entry.r#type == "dir")
+            .map(|entry| entry.name)
+            .collect())
+    }
+
+    async fn list_languages(
+        &self,
+        state: &AppState,
+        project: &Repo,
+    ) -> Result<Vec<LanguageUsage>, Error> {
+        languages::get_languages(state, &state.github_client, project).await
+    }
+
+    async fn list_releases(&self, state: &AppState, project: &Repo) -> Result<Vec<Release>, Error> {
+        releases::get_github_releases(state, &state.github_client, project).await
+    }
+
+    async fn list_commits(&self, state: &AppState, project: &Repo) -> Result<Vec<Commit>, Error> {
+        commits::get_github_commits(state, &state.github_client, project).await
+    }
+}
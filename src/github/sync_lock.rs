@@ -0,0 +1,130 @@
+use std::{future::Future, time::Duration};
+
+use sqlx::{Postgres, Transaction};
+use tokio::sync::Mutex;
+
+use crate::{error::Error, utils::log_error, AppState};
+
+/// The key for the advisory lock held while syncing. Any number works, as long
+/// as nothing else locks the same one.
+const SYNC_LOCK_KEY: i64 = 0x5359_4e43; // "SYNC"
+
+/// A Postgres advisory lock that makes sure only one replica syncs at a time.
+///
+/// It lives in a transaction on its own connection, so it's released when we
+/// finish, when the lock is dropped (the transaction rolls back), or when a
+/// crashed replica's connection goes away. Postgres also closes the connection,
+/// and with it the lock, once it has been idle for `sync_lock_timeout_secs`.
+/// [`SyncLock::hold_while`] touches it well within that, so only a replica
+/// that has stopped running loses the lock that way.
+pub(crate) struct SyncLock {
+    transaction: Mutex<Transaction<'static, Postgres>>,
+    timeout: Duration,
+}
+
+impl SyncLock {
+    /// Takes the lock if nobody else has it.
+    pub(crate) async fn try_acquire(state: &AppState) -> Option<Self> {
+        let mut transaction = begin(state).await?;
+        let acquired = sqlx::query_scalar::<_, bool>("SELECT pg_try_advisory_xact_lock($1);")
+            .bind(SYNC_LOCK_KEY)
+            .fetch_one(&mut *transaction)
+            .await;
+
+        match acquired {
+            Err(err) => {
+                log_error(err);
+                None
+            }
+            Ok(false) => None,
+            Ok(true) => Some(Self::new(state, transaction)),
+        }
+    }
+
+    /// Waits for whoever has the lock to finish, for up to
+    /// `sync_lock_timeout_secs`, then takes it.
+    pub(crate) async fn acquire(state: &AppState) -> Option<Self> {
+        let mut transaction = begin(state).await?;
+        let acquired = sqlx::query("SELECT pg_advisory_xact_lock($1);")
+            .bind(SYNC_LOCK_KEY)
+            .execute(&mut *transaction)
+            .await;
+
+        match acquired {
+            Err(err) => {
+                log_error(err);
+                None
+            }
+            Ok(_) => Some(Self::new(state, transaction)),
+        }
+    }
+
+    fn new(state: &AppState, transaction: Transaction<'static, Postgres>) -> Self {
+        Self {
+            transaction: Mutex::new(transaction),
+            timeout: Duration::from_secs(state.config.sync_lock_timeout_secs),
+        }
+    }
+
+    /// Runs `sync` while renewing the lease on the lock. If the lock's
+    /// connection is lost, another replica may already be syncing, so `sync`
+    /// is dropped before it can commit, which rolls back its transaction.
+    pub(crate) async fn hold_while<T>(
+        &self,
+        sync: impl Future<Output = Result<T, Error>>,
+    ) -> Result<T, Error> {
+        tokio::select! {
+            result = sync => result,
+            _ = self.heartbeat() => Err(Error::SyncLockLost),
+        }
+    }
+
+    /// Touches the lock's connection every third of the timeout. Only returns
+    /// once that fails.
+    async fn heartbeat(&self) {
+        let mut interval = tokio::time::interval((self.timeout / 3).max(Duration::from_secs(1)));
+        loop {
+            interval.tick().await;
+            let mut transaction = self.transaction.lock().await;
+            if let Err(err) = sqlx::query("SELECT 1;").execute(&mut **transaction).await {
+                log_error(err);
+                return;
+            }
+        }
+    }
+
+    pub(crate) async fn release(self) {
+        if let Err(err) = self.transaction.into_inner().rollback().await {
+            log_error(err);
+        }
+    }
+}
+
+async fn begin(state: &AppState) -> Option<Transaction<'static, Postgres>> {
+    let mut transaction = match state.db_connection.begin().await {
+        Err(err) => {
+            log_error(err);
+            return None;
+        }
+        Ok(transaction) => transaction,
+    };
+
+    // Both settings only last as long as the transaction. lock_timeout bounds
+    // how long `acquire` waits, and the idle timeout is the lease on the lock.
+    let timeout_ms = (state.config.sync_lock_timeout_secs * 1000).to_string();
+    let result = sqlx::query(
+        r#"SELECT set_config('lock_timeout', $1, true),
+            set_config('idle_in_transaction_session_timeout', $1, true);"#,
+    )
+    .bind(timeout_ms)
+    .execute(&mut *transaction)
+    .await;
+
+    match result {
+        Err(err) => {
+            log_error(err);
+            None
+        }
+        Ok(_) => Some(transaction),
+    }
+}
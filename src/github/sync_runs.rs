@@ -0,0 +1,115 @@
+use serde_derive::Serialize;
+use sqlx::{
+    types::chrono::{DateTime, Utc},
+    FromRow,
+};
+
+use crate::{error::Error, utils::log_error, AppState};
+
+/// What started a sync.
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum SyncTrigger {
+    /// The scheduler found the data had gone stale.
+    Scheduled,
+    /// GitHub told us about a push through the webhook.
+    Webhook,
+    /// Someone asked for one through the admin endpoint.
+    Manual,
+}
+
+impl SyncTrigger {
+    fn as_str(&self) -> &'static str {
+        match self {
+            SyncTrigger::Scheduled => "scheduled",
+            SyncTrigger::Webhook => "webhook",
+            SyncTrigger::Manual => "manual",
+        }
+    }
+}
+
+/// What a successful sync changed.
+#[derive(Debug, Default)]
+pub(crate) struct SyncSummary {
+    pub(crate) repos_upserted: i32,
+    pub(crate) repos_deleted: i32,
+    pub(crate) blog_posts_upserted: i32,
+    pub(crate) blog_posts_deleted: i32,
+}
+
+#[derive(Serialize, FromRow)]
+pub(crate) struct SyncRun {
+    pub(crate) id: i32,
+    pub(crate) trigger: String,
+    pub(crate) started_at: DateTime<Utc>,
+    /// `None` while the sync is still running.
+    pub(crate) finished_at: Option<DateTime<Utc>>,
+    pub(crate) succeeded: Option<bool>,
+    pub(crate) repos_upserted: i32,
+    pub(crate) repos_deleted: i32,
+    pub(crate) blog_posts_upserted: i32,
+    pub(crate) blog_posts_deleted: i32,
+    pub(crate) error: Option<String>,
+}
+
+/// Records that a sync has started and returns its id. The history is only
+/// informational, so a failure here doesn't stop the sync.
+pub(crate) async fn start(state: &AppState, trigger: SyncTrigger) -> Option<i32> {
+    let result = sqlx::query_scalar::<_, i32>(
+        "INSERT INTO SyncRuns( trigger, started_at ) VALUES ( $1, NOW() ) RETURNING id;",
+    )
+    .bind(trigger.as_str())
+    .fetch_one(&state.db_connection)
+    .await;
+
+    match result {
+        Err(err) => {
+            log_error(err);
+            None
+        }
+        Ok(id) => Some(id),
+    }
+}
+
+pub(crate) async fn finish(state: &AppState, id: i32, result: &Result<SyncSummary, Error>) {
+    let default_summary = SyncSummary::default();
+    let (summary, error) = match result {
+        Err(err) => (&default_summary, Some(err.to_string())),
+        Ok(summary) => (summary, None),
+    };
+
+    let result = sqlx::query(
+        r#"UPDATE SyncRuns SET
+            finished_at = NOW(),
+            succeeded = $2,
+            repos_upserted = $3,
+            repos_deleted = $4,
+            blog_posts_upserted = $5,
+            blog_posts_deleted = $6,
+            error = $7
+        WHERE id=$1;"#,
+    )
+    .bind(id)
+    .bind(error.is_none())
+    .bind(summary.repos_upserted)
+    .bind(summary.repos_deleted)
+    .bind(summary.blog_posts_upserted)
+    .bind(summary.blog_posts_deleted)
+    .bind(error)
+    .execute(&state.db_connection)
+    .await;
+
+    if let Err(err) = result {
+        log_error(err);
+    }
+}
+
+/// The most recent syncs, newest first.
+pub(crate) async fn get_sync_runs(state: &AppState, limit: i64) -> Result<Vec<SyncRun>, Error> {
+    let runs =
+        sqlx::query_as::<_, SyncRun>("SELECT * FROM SyncRuns ORDER BY started_at DESC LIMIT $1;")
+            .bind(limit)
+            .fetch_all(&state.db_connection)
+            .await?;
+
+    Ok(runs)
+}
@@ -0,0 +1,107 @@
+use hmac::{Hmac, Mac};
+use http::{HeaderMap, StatusCode};
+use sha2::Sha256;
+
+use super::sync_runs::SyncTrigger;
+use crate::{error::Error, AppState};
+
+const SIGNATURE_HEADER: &str = "X-Hub-Signature-256";
+const EVENT_HEADER: &str = "X-GitHub-Event";
+
+/// Handles a delivery from the GitHub webhook. Push and repository events kick
+/// off a sync right away so new content doesn't wait for the hourly refresh.
+pub(crate) async fn handle_delivery(
+    state: AppState,
+    headers: &HeaderMap,
+    body: &[u8],
+) -> Result<StatusCode, Error> {
+    let secret = match &state.config.github_webhook_secret {
+        // No secret configured, so the endpoint is turned off.
+        None => return Err(Error::NotFound("The GitHub webhook".to_string())),
+        Some(secret) => secret,
+    };
+
+    let signature = headers
+        .get(SIGNATURE_HEADER)
+        .and_then(|value| value.to_str().ok());
+
+    match signature {
+        Some(signature) if signature_is_valid(secret, body, signature) => {}
+        _ => {
+            println!("Rejected a GitHub webhook delivery with a missing or invalid signature");
+            return Err(Error::Unauthorized(format!(
+                "A valid {} header is required",
+                SIGNATURE_HEADER
+            )));
+        }
+    }
+
+    let event = headers
+        .get(EVENT_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+
+    Ok(match event {
+        // GitHub sends a ping when the webhook is first set up.
+        "ping" => StatusCode::OK,
+        "push" | "repository" => {
+            println!("Received a GitHub {} event. Starting a sync.", event);
+            super::scheduler::request_sync(&state, SyncTrigger::Webhook);
+            StatusCode::ACCEPTED
+        }
+        _ => StatusCode::NO_CONTENT,
+    })
+}
+
+/// Checks a `sha256=<hex digest>` signature against the HMAC of the body. The
+/// comparison is constant-time.
+fn signature_is_valid(secret: &str, body: &[u8], signature: &str) -> bool {
+    let signature = match signature
+        .strip_prefix("sha256=")
+        .and_then(|digest| hex::decode(digest).ok())
+    {
+        None => return false,
+        Some(signature) => signature,
+    };
+
+    let mut mac = match Hmac::<Sha256>::new_from_slice(secret.as_bytes()) {
+        Err(_) => return false,
+        Ok(mac) => mac,
+    };
+    mac.update(body);
+    mac.verify_slice(&signature).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::signature_is_valid;
+
+    // The example delivery from GitHub's webhook docs.
+    const SECRET: &str = "It's a Secret to Everybody";
+    const BODY: &[u8] = b"Hello, World!";
+    const DIGEST: &str = "757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17";
+
+    #[test]
+    fn valid_signature_is_accepted() {
+        assert!(signature_is_valid(SECRET, BODY, &format!("sha256={}", DIGEST)));
+    }
+
+    #[test]
+    fn wrong_digest_is_rejected() {
+        let digest = DIGEST.replace('7', "8");
+        assert!(!signature_is_valid(SECRET, BODY, &format!("sha256={}", digest)));
+        assert!(!signature_is_valid("another secret", BODY, &format!("sha256={}", DIGEST)));
+    }
+
+    #[test]
+    fn signature_without_prefix_is_rejected() {
+        assert!(!signature_is_valid(SECRET, BODY, DIGEST));
+        assert!(!signature_is_valid(SECRET, BODY, &format!("sha1={}", DIGEST)));
+    }
+
+    #[test]
+    fn signature_with_bad_hex_is_rejected() {
+        assert!(!signature_is_valid(SECRET, BODY, "sha256=not-hex"));
+        assert!(!signature_is_valid(SECRET, BODY, &format!("sha256={}0", DIGEST)));
+    }
+}
@@ -1,16 +1,42 @@
 use axum::{
-    extract::{DefaultBodyLimit, Path, State},
-    response::Html,
+    body::Bytes,
+    extract::{
+        rejection::{JsonRejection, QueryRejection},
+        DefaultBodyLimit, Path, Query, State,
+    },
+    response::{Html, IntoResponse, Response},
     routing::{get, post},
     Router, Json,
 };
-use github::{BlogPost, Repo};
+use config::Config;
+use content_source::ContentSource;
+use crates_io::{CrateStats, CratesIoClient};
+use error::Error;
+use github::{
+    client::GitHubClient,
+    commits::Commit,
+    languages::LanguageUsage,
+    releases::Release,
+    sync_runs::{SyncRun, SyncTrigger},
+    BlogPost, Repo,
+};
 use pulldown_cmark::{html, Options, Parser};
 use serde_derive::{Deserialize, Serialize};
-use sqlx::{postgres::PgPoolOptions, PgPool};
-use http::{Method, StatusCode};
+use sqlx::{
+    migrate::{Migrate, Migrator},
+    postgres::PgPoolOptions,
+    types::chrono::{DateTime, Utc},
+    PgPool,
+};
+use http::{header::AUTHORIZATION, HeaderMap, Method, StatusCode};
+use std::{cmp::Reverse, collections::HashSet, sync::Arc};
+use tokio::sync::mpsc;
 use tower_http::{cors::{Any, CorsLayer}, services::{ServeDir, ServeFile}};
 
+mod config;
+mod content_source;
+mod crates_io;
+mod error;
 mod github;
 mod utils;
 
@@ -24,6 +50,7 @@ pub(crate) const ACCEPT_INVALID_CERTS: bool = true;
 async fn main() {
     // First, parse the .env file for our environment setup.
     dotenvy::dotenv().ok();
+    let config = Config::from_env();
 
     // We create a single connection pool for SQLx that's shared across the whole application.
     // This saves us from opening a new connection for every API call, which is wasteful.
@@ -39,11 +66,50 @@ async fn main() {
         .await
         .unwrap_or_else(|err| panic!("Could not connect to dabase_url. Error: \n{}", err));
 
+    // `--dry-run` prints what a sync would change and exits without serving.
+    let dry_run = std::env::args().any(|arg| arg == "--dry-run");
+
     // Run any SQL migrations to get the DB into the correct state
-    sqlx::migrate!("./migrations")
-        .run(&pool)
-        .await
-        .unwrap_or_else(|err| panic!("Failed to migrate the database. Error: \n{}", err));
+    let migrator = sqlx::migrate!("./migrations");
+    if dry_run {
+        ensure_migrated(&pool, &migrator).await;
+    } else {
+        migrator
+            .run(&pool)
+            .await
+            .unwrap_or_else(|err| panic!("Failed to migrate the database. Error: \n{}", err));
+    }
+
+    let github_client = GitHubClient::new(&config)
+        .unwrap_or_else(|err| panic!("Failed to build the GitHub client. Error: \n{}", err));
+    let crates_io_client = CratesIoClient::new(&config)
+        .unwrap_or_else(|err| panic!("Failed to build the crates.io client. Error: \n{}", err));
+    let content_source = content_source::from_config(&config)
+        .unwrap_or_else(|err| panic!("Failed to set up the content source. Error: \n{}", err));
+
+    // All syncing happens on one background task. Request handlers only read.
+    let (sync_sender, sync_receiver) = mpsc::channel(1);
+    let state = AppState::new(
+        pool,
+        config,
+        github_client,
+        crates_io_client,
+        content_source,
+        sync_sender,
+    );
+
+    if dry_run {
+        match github::plan_github_sync(&state).await {
+            Err(err) => panic!("Failed to plan a sync. Error: \n{}", err),
+            Ok(report) => println!(
+                "{}",
+                serde_json::to_string_pretty(&report).expect("the report to serialize")
+            ),
+        }
+        return;
+    }
+
+    github::scheduler::start(state.clone(), sync_receiver);
 
     let mut current_dir = std::env::current_dir().expect("Failed to detect current directory.");
     println!("{}", current_dir.to_string_lossy());
@@ -57,12 +123,20 @@ async fn main() {
         .route("/home", get(home))
         .route("/projects_json", get(projects))
         .route("/projects_json/:project", get(project))
+        .route("/projects_json/:project/releases", get(project_releases))
+        .route("/projects_json/:project/commits", get(project_commits))
+        .route("/activity_json", get(activity))
+        .route("/languages_json", get(languages))
         .route("/blog_json", get(blog))
         .route("/blog_json/:blog", get(blog_post))
         .route("/parsejson", post(parse_json))
         .route("/formatjson", post(format_json))
+        .route("/webhooks/github", post(github_webhook))
+        .route("/admin/sync", post(trigger_sync))
+        .route("/admin/sync/dry_run", post(dry_run_sync))
+        .route("/admin/sync_runs", get(sync_runs))
         // Attach our connection pool to every endpoint so the endpoints can query the DB.
-        .with_state(AppState::new(pool))
+        .with_state(state)
         .layer(
             // Add CORS so it doesn't block our requests from the browser
             CorsLayer::new()
@@ -83,13 +157,46 @@ async fn main() {
         .unwrap_or_else(|err| panic!("Failed to start app. Error: \n{}", err));
 }
 
-async fn home(State(state): State<AppState>) -> Result<Html<String>, StatusCode> {
-    match github::get_home(state.clone()).await {
-        None => Err(StatusCode::INTERNAL_SERVER_ERROR),
-        Some(data) => Ok(Html(parse_md_to_html(&data.content))),
+/// Panics unless every migration has been applied. A dry run mustn't write to
+/// the DB, and some migrations rewrite rows, so it doesn't migrate.
+async fn ensure_migrated(pool: &PgPool, migrator: &Migrator) {
+    let mut connection = pool
+        .acquire()
+        .await
+        .unwrap_or_else(|err| panic!("Could not connect to the database. Error: \n{}", err));
+    let applied = connection
+        .list_applied_migrations()
+        .await
+        .unwrap_or_else(|err| {
+            panic!(
+                "Failed to read the applied migrations. Has the server been started without --dry-run yet? Error: \n{}",
+                err
+            )
+        })
+        .into_iter()
+        .map(|migration| migration.version)
+        .collect::<HashSet<_>>();
+
+    let pending = migrator
+        .iter()
+        .filter(|migration| {
+            !migration.migration_type.is_down_migration() && !applied.contains(&migration.version)
+        })
+        .map(|migration| format!("{} {}", migration.version, migration.description))
+        .collect::<Vec<_>>();
+    if !pending.is_empty() {
+        panic!(
+            "--dry-run doesn't migrate the database, and these migrations are pending: {}. Start the server without --dry-run once to apply them.",
+            pending.join(", ")
+        );
     }
 }
 
+async fn home(State(state): State<AppState>) -> Result<Html<String>, Error> {
+    let data = github::get_home(state.clone()).await?;
+    Ok(Html(parse_md_to_html(&data.content)))
+}
+
 #[derive(Serialize,Deserialize)]
 pub(crate) struct RepoDTO {
     pub(crate) id: i64,
@@ -98,66 +205,290 @@ pub(crate) struct RepoDTO {
     pub(crate) html_url: String,
     pub(crate) description: String,
     pub(crate) readme: Option<String>,
+    pub(crate) topics: Vec<String>,
+    pub(crate) language: Option<String>,
+    pub(crate) stargazers_count: i32,
+    pub(crate) forks_count: i32,
+    pub(crate) license_spdx_id: Option<String>,
+    pub(crate) homepage: Option<String>,
+    pub(crate) created_at: Option<DateTime<Utc>>,
+    pub(crate) pushed_at: DateTime<Utc>,
+    pub(crate) archived: bool,
+    pub(crate) fork: bool,
+    pub(crate) crate_names: Vec<String>,
+    pub(crate) crates: Vec<CrateDTO>,
+    pub(crate) languages: Vec<LanguageDTO>,
     pub(crate) url_safe_name: String,
     pub(crate) additional_nav_elements: Vec<NavBarElement>,
+    /// Where the project sits among the featured ones, starting at 0. `None`
+    /// for projects that aren't featured.
+    pub(crate) featured_rank: Option<usize>,
 }
 
 impl From<Repo> for RepoDTO {
     fn from(value: Repo) -> Self {
         Self {
-            additional_nav_elements: vec![
-                Some(NavBarElement {
-                    display_text: "Source Code".to_string(),
-                    href: value.html_url.to_string(),
-                }),
-                match value.name.as_str() {
-                    "tree-iterators-rs" => Some(NavBarElement {
-                        display_text: "Crates.io".to_string(),
-                        href: "https://crates.io/crates/tree_iterators_rs".to_string(),
-                    }),
-                    "json-formatter" => Some(NavBarElement {
-                        display_text: "Crates.io".to_string(),
-                        href: "https://crates.io/crates/toy-json-formatter".to_string(),
-                    }),
-                    _ => None,
-                }
-            ].into_iter()
-            .flat_map(|vec| vec)
-            .collect(),
+            additional_nav_elements: get_nav_elements(&value),
             url_safe_name: get_url_safe_name(&value.name),
+            featured_rank: None,
             id: value.id,
             name: value.name,
             url: value.url,
             html_url: value.html_url,
             description: value.description,
-            readme: if let Some(readme) = value.readme { 
-                Some(parse_md_to_html(&readme)) 
-            } else { 
-                None 
-            },
+            readme: value.readme.map(|readme| parse_md_to_html(&readme)),
+            topics: value.topics,
+            language: value.language,
+            stargazers_count: value.stargazers_count,
+            forks_count: value.forks_count,
+            license_spdx_id: value.license_spdx_id,
+            homepage: value.homepage,
+            created_at: value.created_at,
+            pushed_at: value.pushed_at,
+            archived: value.archived,
+            fork: value.fork,
+            crate_names: value.crate_names,
+            crates: value.crates.into_iter().map(|stats| stats.into()).collect(),
+            languages: to_language_dtos(value.languages),
         }
     }
 }
 
-async fn projects(State(state): State<AppState>) -> Result<Json<Vec<RepoDTO>>, StatusCode> {
-    match github::get_repos(state.clone()).await {
-        None => Err(StatusCode::NOT_FOUND),
-        Some(data) => {
-            Ok(Json(data.into_iter().map(|repo| repo.into()).collect()))
+#[derive(Serialize, Deserialize)]
+pub(crate) struct CrateDTO {
+    pub(crate) name: String,
+    pub(crate) latest_version: String,
+    pub(crate) downloads: i64,
+    pub(crate) recent_downloads: Option<i64>,
+    pub(crate) rust_version: Option<String>,
+    pub(crate) edition: Option<String>,
+}
+
+impl From<CrateStats> for CrateDTO {
+    fn from(value: CrateStats) -> Self {
+        Self {
+            name: value.name,
+            latest_version: value.latest_version,
+            downloads: value.downloads,
+            recent_downloads: value.recent_downloads,
+            rust_version: value.rust_version,
+            edition: value.edition,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct LanguageDTO {
+    pub(crate) language: String,
+    /// Missing when the source only reports shares, like GitLab.
+    pub(crate) bytes: Option<i64>,
+    /// This language's share of the whole, from 0 to 100.
+    pub(crate) percentage: f64,
+}
+
+/// Works out each language's percentage from the byte counts, or from the
+/// shares when any byte count is missing. Summed shares weigh every repo the
+/// same, however big it is.
+fn to_language_dtos(languages: Vec<LanguageUsage>) -> Vec<LanguageDTO> {
+    let has_bytes = languages.iter().all(|language| language.bytes.is_some());
+    let weight = |language: &LanguageUsage| {
+        if has_bytes {
+            language.bytes.unwrap_or_default() as f64
+        } else {
+            language.share.unwrap_or_default()
+        }
+    };
+
+    let total = languages.iter().map(weight).sum::<f64>();
+    languages
+        .into_iter()
+        .map(|language| LanguageDTO {
+            percentage: if total == 0.0 {
+                0.0
+            } else {
+                // Rounded to one decimal place.
+                (weight(&language) * 1000.0 / total).round() / 10.0
+            },
+            language: language.language,
+            bytes: language.bytes,
+        })
+        .collect()
+}
+
+async fn languages(State(state): State<AppState>) -> Result<Json<Vec<LanguageDTO>>, Error> {
+    let totals = github::languages::get_language_totals(&state).await?;
+    Ok(Json(to_language_dtos(totals)))
+}
+
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum ProjectSort {
+    #[default]
+    Name,
+    /// Most recently pushed first.
+    Pushed,
+    /// Most stars first.
+    Stars,
+}
+
+#[derive(Deserialize)]
+struct ProjectsQuery {
+    #[serde(default)]
+    sort: ProjectSort,
+}
+
+/// The links shown on a project: its source, the crates.io and docs.rs pages
+/// of any crates found in it, then any links added by hand.
+fn get_nav_elements(repo: &Repo) -> Vec<NavBarElement> {
+    let mut nav_elements = vec![NavBarElement {
+        display_text: "Source Code".to_string(),
+        href: repo.html_url.to_string(),
+    }];
+
+    for crate_name in &repo.crate_names {
+        // Only say which crate a link is for when there's more than one.
+        let (crates_io_text, docs_rs_text) = if repo.crate_names.len() == 1 {
+            ("Crates.io".to_string(), "Docs.rs".to_string())
+        } else {
+            (
+                format!("{} on Crates.io", crate_name),
+                format!("{} on Docs.rs", crate_name),
+            )
+        };
+        nav_elements.push(NavBarElement {
+            display_text: crates_io_text,
+            href: format!("https://crates.io/crates/{}", crate_name),
+        });
+        nav_elements.push(NavBarElement {
+            display_text: docs_rs_text,
+            href: format!("https://docs.rs/{}", crate_name),
+        });
+    }
+
+    for link in &repo.links {
+        // Hand-added links may predate the crate detection.
+        if !nav_elements.iter().any(|element| element.href == link.href) {
+            nav_elements.push(NavBarElement {
+                display_text: link.display_text.clone(),
+                href: link.href.clone(),
+            });
         }
     }
+
+    nav_elements
+}
+
+async fn projects(
+    State(state): State<AppState>,
+    query: Result<Query<ProjectsQuery>, QueryRejection>,
+) -> Result<Json<Vec<RepoDTO>>, Error> {
+    let Query(query) = query.map_err(|rejection| Error::BadRequest(rejection.body_text()))?;
+    let mut data = github::get_repos(state.clone()).await?;
+    // The DB hands them back sorted by name already, and the sorts are stable.
+    match query.sort {
+        ProjectSort::Name => {}
+        ProjectSort::Pushed => data.sort_by_key(|repo| Reverse(repo.pushed_at)),
+        ProjectSort::Stars => data.sort_by_key(|repo| Reverse(repo.stargazers_count)),
+    }
+
+    let mut data = data
+        .into_iter()
+        .map(|repo| to_repo_dto(&state.config, repo))
+        .collect::<Vec<_>>();
+    // Featured projects always come first, in the order they were configured.
+    data.sort_by_key(|repo| (repo.featured_rank.is_none(), repo.featured_rank));
+    Ok(Json(data))
 }
 
 async fn project(
     State(state): State<AppState>,
     Path(project): Path<String>,
-) -> Result<Json<RepoDTO>, StatusCode> {
-    match github::get_repo(&state.clone(), &project).await {
-        None => Err(StatusCode::NOT_FOUND),
-        Some(repo) => Ok(Json(repo.into()))
+) -> Result<Json<RepoDTO>, Error> {
+    let repo = github::get_repo(&state.clone(), &project).await?;
+    Ok(Json(to_repo_dto(&state.config, repo)))
+}
+
+fn to_repo_dto(config: &Config, repo: Repo) -> RepoDTO {
+    let featured_rank = config.featured_rank(&repo.name);
+    RepoDTO {
+        featured_rank,
+        ..repo.into()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct ReleaseDTO {
+    pub(crate) id: i64,
+    pub(crate) tag_name: String,
+    pub(crate) name: Option<String>,
+    pub(crate) body: Option<String>,
+    pub(crate) html_url: String,
+    pub(crate) published_at: Option<DateTime<Utc>>,
+    pub(crate) prerelease: bool,
+}
+
+impl From<Release> for ReleaseDTO {
+    fn from(value: Release) -> Self {
+        Self {
+            id: value.id,
+            tag_name: value.tag_name,
+            name: value.name,
+            body: value.body.map(|body| parse_md_to_html(&body)),
+            html_url: value.html_url,
+            published_at: value.published_at,
+            prerelease: value.prerelease,
+        }
+    }
+}
+
+async fn project_releases(
+    State(state): State<AppState>,
+    Path(project): Path<String>,
+) -> Result<Json<Vec<ReleaseDTO>>, Error> {
+    let repo = github::get_repo(&state, &project).await?;
+    let releases = github::releases::get_releases(&state, repo.id).await?;
+    Ok(Json(releases.into_iter().map(|release| release.into()).collect()))
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct CommitDTO {
+    pub(crate) sha: String,
+    pub(crate) headline: String,
+    pub(crate) author: String,
+    pub(crate) committed_at: DateTime<Utc>,
+    pub(crate) html_url: String,
+    pub(crate) repo_name: String,
+    pub(crate) repo_url_safe_name: String,
+}
+
+impl From<Commit> for CommitDTO {
+    fn from(value: Commit) -> Self {
+        Self {
+            repo_url_safe_name: get_url_safe_name(&value.repo_name),
+            sha: value.sha,
+            headline: value.headline,
+            author: value.author,
+            committed_at: value.committed_at,
+            html_url: value.html_url,
+            repo_name: value.repo_name,
+        }
     }
 }
 
+async fn project_commits(
+    State(state): State<AppState>,
+    Path(project): Path<String>,
+) -> Result<Json<Vec<CommitDTO>>, Error> {
+    let repo = github::get_repo(&state, &project).await?;
+    let commits = github::commits::get_commits(&state, repo.id).await?;
+    Ok(Json(commits.into_iter().map(|commit| commit.into()).collect()))
+}
+
+async fn activity(State(state): State<AppState>) -> Result<Json<Vec<CommitDTO>>, Error> {
+    let commits = github::commits::get_recent_activity(&state, 30).await?;
+    Ok(Json(commits.into_iter().map(|commit| commit.into()).collect()))
+}
+
 #[derive(Serialize, Deserialize)]
 pub(crate) struct BlogPostDTO {
     pub(crate) id: i32,
@@ -166,53 +497,104 @@ pub(crate) struct BlogPostDTO {
     pub(crate) sha: String,
     pub(crate) description: String,
     pub(crate) content: String,
+    pub(crate) path: String,
+    pub(crate) updated_at: Option<DateTime<Utc>>,
     pub(crate) url_safe_name: String,
 }
 
 impl From<BlogPost> for BlogPostDTO {
     fn from(value: BlogPost) -> Self {
         BlogPostDTO {
-            url_safe_name: get_url_safe_name(&value.name),
+            url_safe_name: value.alphanumeric_name.clone(),
             id: value.id,
             name: value.name,
             alphanumeric_name: value.alphanumeric_name,
             sha: value.sha,
             description: value.description,
             content: parse_md_to_html(&value.content),
+            path: value.path,
+            updated_at: value.updated_at,
         }
     }
 }
 
-async fn blog(State(state): State<AppState>) -> Result<Json<Vec<BlogPostDTO>>, StatusCode> {
-    match github::get_blog_posts(state.clone()).await {
-        None => Err(StatusCode::NOT_FOUND),
-        Some(mut data) => {
-            data.sort_by(|post1, post2| post2.description.cmp(&post1.description));
-            Ok(Json(data.into_iter().map(|post| post.into()).collect()))
-        }
-    }
+async fn blog(State(state): State<AppState>) -> Result<Json<Vec<BlogPostDTO>>, Error> {
+    let mut data = github::get_blog_posts(state.clone()).await?;
+    data.sort_by(|post1, post2| post2.description.cmp(&post1.description));
+    Ok(Json(data.into_iter().map(|post| post.into()).collect()))
 }
 
 async fn blog_post(
     State(state): State<AppState>,
     Path(blog): Path<String>,
-) -> Result<Json<BlogPostDTO>, StatusCode> {
-    match github::get_blog_post(&state.clone(), &blog).await {
-        None => Err(StatusCode::NOT_FOUND),
-        Some(blog_post) => Ok(Json(blog_post.into()))
-    }
+) -> Result<Json<BlogPostDTO>, Error> {
+    let blog_post = github::get_blog_post(&state.clone(), &blog).await?;
+    Ok(Json(blog_post.into()))
 }
 
-async fn parse_json(json: Json<JsonFormData>) -> Json<String> {
-    let jsons;
-    match json.0.format {
-        JsonFormat::JsonLines => {
-            jsons = json.0.json.lines().collect();
-        }
-        JsonFormat::JsonStandard => {
-            jsons = vec![&json.0.json[..]];
-        }
+async fn github_webhook(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<StatusCode, Error> {
+    github::webhook::handle_delivery(state, &headers, &body).await
+}
+
+/// The sync history is for admins only. The errors in it name our
+/// infrastructure, like the DB and the local content paths.
+async fn sync_runs(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<SyncRun>>, Error> {
+    authorize_admin(&state, &headers)?;
+    let runs = github::sync_runs::get_sync_runs(&state, 50).await?;
+    Ok(Json(runs))
+}
+
+async fn trigger_sync(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<StatusCode, Error> {
+    authorize_admin(&state, &headers)?;
+    github::scheduler::request_sync(&state, SyncTrigger::Manual);
+    Ok(StatusCode::ACCEPTED)
+}
+
+async fn dry_run_sync(State(state): State<AppState>, headers: HeaderMap) -> Result<Response, Error> {
+    authorize_admin(&state, &headers)?;
+    let report = github::plan_github_sync(&state).await?;
+    Ok(Json(report).into_response())
+}
+
+/// Admin endpoints are turned off unless ADMIN_TOKEN is set, and then they need
+/// it as a bearer token.
+fn authorize_admin(state: &AppState, headers: &HeaderMap) -> Result<(), Error> {
+    let admin_token = match &state.config.admin_token {
+        None => return Err(Error::NotFound("The admin endpoints".to_string())),
+        Some(admin_token) => admin_token,
+    };
+
+    let provided_token = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+
+    match provided_token {
+        Some(provided_token) if utils::constant_time_eq(provided_token, admin_token) => Ok(()),
+        _ => Err(Error::Unauthorized(
+            "A valid admin bearer token is required".to_string(),
+        )),
     }
+}
+
+async fn parse_json(
+    json: Result<Json<JsonFormData>, JsonRejection>,
+) -> Result<Json<String>, Error> {
+    let json = json.map_err(|rejection| Error::BadRequest(rejection.body_text()))?;
+    let jsons: Vec<&str> = match json.0.format {
+        JsonFormat::JsonLines => json.0.json.lines().collect(),
+        JsonFormat::JsonStandard => vec![&json.0.json[..]],
+    };
 
     let results = jsons.into_iter().map(|json| {
         let result = toy_json_formatter::parse(json);
@@ -234,24 +616,33 @@ async fn parse_json(json: Json<JsonFormData>) -> Json<String> {
     .collect::<Vec<_>>();
 
     let json_string = serde_json::to_string(&results).expect("JSON value to always be JSON serializable.");
-    Json(json_string)
+    Ok(Json(json_string))
 }
 
-async fn format_json(json: Json<String>) -> Json<String> {
-    Json(toy_json_formatter::format(json.as_str()).0)
+async fn format_json(json: Result<Json<String>, JsonRejection>) -> Result<Json<String>, Error> {
+    let Json(json) = json.map_err(|rejection| Error::BadRequest(rejection.body_text()))?;
+    Ok(Json(toy_json_formatter::format(json.as_str()).0))
 }
 
 fn get_url_safe_name(name: &str) -> String {
     name.chars()
-        .filter(|char| match char {
-            'a'..='z' | 'A'..='Z' | '0'..='9' => true,
-            _ => false,
-        })
+        .filter(|char| char.is_ascii_alphanumeric())
         .collect()
 }
 
+/// The URL name of a blog post. Each folder becomes its own part, so
+/// `drafts/post.md` and `draftspost.md` don't collide.
+fn get_post_url_safe_name(path: &str) -> String {
+    path.trim_end_matches(".md")
+        .split('/')
+        .map(get_url_safe_name)
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
 fn parse_md_to_html(md: &str) -> String {
-    let parser = Parser::new_ext(&md, Options::empty());
+    let parser = Parser::new_ext(md, Options::empty());
     let mut html_output = String::new();
     html::push_html(&mut html_output, parser);
     html_output
@@ -266,12 +657,33 @@ struct NavBarElement {
 #[derive(Clone)]
 struct AppState {
     db_connection: PgPool,
+    config: Arc<Config>,
+    github_client: Arc<GitHubClient>,
+    crates_io_client: Arc<CratesIoClient>,
+    /// Where syncs read the projects and blog posts from.
+    content_source: Arc<dyn ContentSource>,
+    sync_requests: mpsc::Sender<SyncTrigger>,
+    /// Set while planning a dry run, which mustn't write anything.
+    dry_run: bool,
 }
 
 impl AppState {
-    fn new(pool: PgPool) -> Self {
+    fn new(
+        pool: PgPool,
+        config: Config,
+        github_client: GitHubClient,
+        crates_io_client: CratesIoClient,
+        content_source: Arc<dyn ContentSource>,
+        sync_requests: mpsc::Sender<SyncTrigger>,
+    ) -> Self {
         Self {
             db_connection: pool,
+            config: Arc::new(config),
+            github_client: Arc::new(github_client),
+            crates_io_client: Arc::new(crates_io_client),
+            content_source,
+            sync_requests,
+            dry_run: false,
         }
     }
 }
@@ -287,3 +699,62 @@ enum JsonFormat {
     JsonStandard,
     JsonLines,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{get_post_url_safe_name, to_language_dtos, LanguageUsage};
+
+    #[test]
+    fn post_url_safe_names_keep_folders_apart() {
+        assert_eq!(get_post_url_safe_name("My Post.md"), "MyPost");
+        assert_eq!(get_post_url_safe_name("drafts/post.md"), "drafts-post");
+        assert_ne!(
+            get_post_url_safe_name("drafts/post.md"),
+            get_post_url_safe_name("draftspost.md")
+        );
+    }
+
+    #[test]
+    fn language_percentages_come_from_bytes() {
+        let languages = to_language_dtos(vec![
+            LanguageUsage {
+                language: "Rust".to_string(),
+                bytes: Some(3),
+                share: None,
+            },
+            LanguageUsage {
+                language: "Shell".to_string(),
+                bytes: Some(1),
+                share: None,
+            },
+        ]);
+        let percentages = languages
+            .iter()
+            .map(|language| language.percentage)
+            .collect::<Vec<_>>();
+        assert_eq!(percentages, vec![75.0, 25.0]);
+    }
+
+    #[test]
+    fn language_percentages_fall_back_to_shares() {
+        // Two GitLab projects' worth of shares, summed.
+        let languages = to_language_dtos(vec![
+            LanguageUsage {
+                language: "Rust".to_string(),
+                bytes: None,
+                share: Some(150.0),
+            },
+            LanguageUsage {
+                language: "Shell".to_string(),
+                bytes: None,
+                share: Some(50.0),
+            },
+        ]);
+        let percentages = languages
+            .iter()
+            .map(|language| language.percentage)
+            .collect::<Vec<_>>();
+        assert_eq!(percentages, vec![75.0, 25.0]);
+        assert!(languages.iter().all(|language| language.bytes.is_none()));
+    }
+}
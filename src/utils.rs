@@ -4,3 +4,86 @@ pub(crate) fn log_error<E: Display + Debug>(error: E) {
     println!("{:?}", error);
     println!("{}", error);
 }
+
+/// Compares two secrets without bailing out at the first difference, so the
+/// response time doesn't leak how much of a guess was right.
+pub(crate) fn constant_time_eq(left: &str, right: &str) -> bool {
+    left.len() == right.len()
+        && left
+            .bytes()
+            .zip(right.bytes())
+            .fold(0, |difference, (left, right)| difference | (left ^ right))
+            == 0
+}
+
+/// Matches `text` against a glob where `*` matches any run of characters and
+/// `?` matches exactly one. Case is ignored, like GitHub does for repo names.
+pub(crate) fn glob_matches(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.to_ascii_lowercase().chars().collect::<Vec<_>>();
+    let text = text.to_ascii_lowercase().chars().collect::<Vec<_>>();
+
+    let (mut pattern_index, mut text_index) = (0, 0);
+    // Where the last `*` was, and how much of the text it has swallowed so far.
+    let mut backtrack = None;
+    while text_index < text.len() {
+        match pattern.get(pattern_index) {
+            Some('*') => {
+                backtrack = Some((pattern_index, text_index));
+                pattern_index += 1;
+            }
+            Some(&character) if character == '?' || character == text[text_index] => {
+                pattern_index += 1;
+                text_index += 1;
+            }
+            _ => match backtrack {
+                None => return false,
+                Some((star_index, star_text_index)) => {
+                    // Let the `*` swallow one more character and try again.
+                    pattern_index = star_index + 1;
+                    text_index = star_text_index + 1;
+                    backtrack = Some((star_index, star_text_index + 1));
+                }
+            },
+        }
+    }
+
+    pattern[pattern_index..]
+        .iter()
+        .all(|&character| character == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn star_matches_any_run_of_characters() {
+        assert!(glob_matches("*-rs", "serde-rs"));
+        assert!(glob_matches("*-rs", "-rs"));
+        assert!(!glob_matches("*-rs", "serde-rs-old"));
+        assert!(glob_matches("a*b*c", "abc"));
+        assert!(glob_matches("a*b*c", "a-b-b-c"));
+        assert!(!glob_matches("a*b*c", "a-c-b"));
+    }
+
+    #[test]
+    fn trailing_star_matches_the_rest() {
+        assert!(glob_matches("dotfiles*", "dotfiles"));
+        assert!(glob_matches("dotfiles*", "dotfiles-old"));
+        assert!(!glob_matches("dotfiles*", "my-dotfiles"));
+        assert!(glob_matches("*", ""));
+    }
+
+    #[test]
+    fn question_mark_matches_exactly_one_character() {
+        assert!(glob_matches("v?", "v2"));
+        assert!(!glob_matches("v?", "v"));
+        assert!(!glob_matches("v?", "v10"));
+    }
+
+    #[test]
+    fn case_is_ignored() {
+        assert!(glob_matches("Crate-*", "crate-Server"));
+        assert!(glob_matches("crate", "CRATE"));
+    }
+}